@@ -0,0 +1,68 @@
+use super::types::*;
+use super::Gl;
+use std::cell::Cell;
+use std::ffi::CStr;
+
+/// Features detected on first use. They do not change for a context.
+#[derive(Default)]
+pub(crate) struct Capabilities {
+    direct_state_access: Cell<Option<bool>>,
+    anisotropic_filtering: Cell<Option<bool>>,
+}
+
+impl Gl {
+    /// (major, minor) version of the context.
+    pub fn version(&self) -> (GLint, GLint) {
+        let mut major: GLint = 0;
+        let mut minor: GLint = 0;
+        unsafe {
+            self.GetIntegerv(super::MAJOR_VERSION, &mut major);
+            self.GetIntegerv(super::MINOR_VERSION, &mut minor);
+        }
+        (major, minor)
+    }
+
+    /// Whether the context advertises the extension `name`, e.g.
+    /// `"GL_ARB_buffer_storage"`.
+    pub fn has_extension(&self, name: &str) -> bool {
+        let mut count: GLint = 0;
+        unsafe {
+            self.GetIntegerv(super::NUM_EXTENSIONS, &mut count);
+        }
+        (0..count as GLuint).any(|i| {
+            let ext = unsafe { self.GetStringi(super::EXTENSIONS, i) };
+            !ext.is_null()
+                && unsafe { CStr::from_ptr(ext as *const std::os::raw::c_char) }.to_bytes()
+                    == name.as_bytes()
+        })
+    }
+
+    /// Whether the `glNamed*` and `glVertexArray*` direct state access
+    /// functions can be used, i.e. GL 4.5 or ARB_direct_state_access. Only
+    /// the first call queries the context.
+    pub fn has_direct_state_access(&self) -> bool {
+        detect(&self.capabilities.direct_state_access, || {
+            self.version() >= (4, 5) || self.has_extension("GL_ARB_direct_state_access")
+        })
+    }
+
+    /// Whether `GL_TEXTURE_MAX_ANISOTROPY` can be set, i.e. GL 4.6 or
+    /// EXT/ARB_texture_filter_anisotropic. Only the first call queries the
+    /// context.
+    pub fn has_anisotropic_filtering(&self) -> bool {
+        detect(&self.capabilities.anisotropic_filtering, || {
+            self.version() >= (4, 6)
+                || self.has_extension("GL_EXT_texture_filter_anisotropic")
+                || self.has_extension("GL_ARB_texture_filter_anisotropic")
+        })
+    }
+}
+
+fn detect<F: FnOnce() -> bool>(cached: &Cell<Option<bool>>, query: F) -> bool {
+    if let Some(supported) = cached.get() {
+        return supported;
+    }
+    let supported = query();
+    cached.set(Some(supported));
+    supported
+}
@@ -1,13 +1,23 @@
 
 pub use bindings::*;
 pub use bindings::Gl as InnerGl;
+pub use state::ElidedCalls;
 
 use std::rc::Rc;
 use std::ops::Deref;
 
+mod capabilities;
+mod state;
+
+/// Function pointers of one context. Clones share them and a cache of the
+/// bound program, vertex array and buffers, the enabled capabilities, blend
+/// and depth state and the viewport, see the lower-case setters, and the
+/// detected capabilities.
 #[derive(Clone)]
 pub struct Gl {
     inner: Rc<bindings::Gl>,
+    state: Rc<state::State>,
+    capabilities: Rc<capabilities::Capabilities>,
 }
 
 impl Gl {
@@ -17,6 +27,8 @@ impl Gl {
     {
         Gl {
             inner: Rc::new(bindings::Gl::load_with(loadfn)),
+            state: Rc::new(state::State::default()),
+            capabilities: Rc::new(capabilities::Capabilities::default()),
         }
     }
 }
@@ -0,0 +1,303 @@
+use super::types::*;
+use super::Gl;
+use std::cell::{Cell, RefCell};
+
+/// Number of calls that the cached setters of `Gl` skipped because the
+/// context already was in the requested state.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ElidedCalls {
+    pub use_program: usize,
+    pub bind_vertex_array: usize,
+    pub bind_buffer: usize,
+    pub enable_disable: usize,
+    pub blend_func: usize,
+    pub depth_func: usize,
+    pub depth_mask: usize,
+    pub viewport: usize,
+}
+
+impl ElidedCalls {
+    pub fn total(&self) -> usize {
+        self.use_program
+            + self.bind_vertex_array
+            + self.bind_buffer
+            + self.enable_disable
+            + self.blend_func
+            + self.depth_func
+            + self.depth_mask
+            + self.viewport
+    }
+}
+
+/// State last set through the cached setters. `None` and missing entries
+/// mean unknown, so the next call goes through.
+#[derive(Default)]
+pub(crate) struct State {
+    program: Cell<Option<GLuint>>,
+    vertex_array: Cell<Option<GLuint>>,
+    buffers: RefCell<Vec<(GLenum, GLuint)>>,
+    capabilities: RefCell<Vec<(GLenum, bool)>>,
+    blend_func: Cell<Option<(GLenum, GLenum)>>,
+    depth_func: Cell<Option<GLenum>>,
+    depth_mask: Cell<Option<bool>>,
+    viewport: Cell<Option<(GLint, GLint, GLsizei, GLsizei)>>,
+    elided: Cell<ElidedCalls>,
+}
+
+impl State {
+    /// Stores `value` and returns true if it differs from the cached one,
+    /// otherwise counts the call in `counter` and returns false.
+    fn update<T: Copy + PartialEq>(
+        &self,
+        cell: &Cell<Option<T>>,
+        value: T,
+        counter: fn(&mut ElidedCalls) -> &mut usize,
+    ) -> bool {
+        if cell.get() == Some(value) {
+            self.elide(counter);
+            return false;
+        }
+        cell.set(Some(value));
+        true
+    }
+
+    /// Like `update` for state that is kept per target or capability.
+    fn update_entry<K: Copy + PartialEq, V: Copy + PartialEq>(
+        &self,
+        entries: &RefCell<Vec<(K, V)>>,
+        key: K,
+        value: V,
+        counter: fn(&mut ElidedCalls) -> &mut usize,
+    ) -> bool {
+        let mut entries = entries.borrow_mut();
+        match entries.iter_mut().find(|entry| entry.0 == key) {
+            Some(entry) if entry.1 == value => {
+                self.elide(counter);
+                false
+            }
+            Some(entry) => {
+                entry.1 = value;
+                true
+            }
+            None => {
+                entries.push((key, value));
+                true
+            }
+        }
+    }
+
+    fn elide(&self, counter: fn(&mut ElidedCalls) -> &mut usize) {
+        let mut elided = self.elided.get();
+        *counter(&mut elided) += 1;
+        self.elided.set(elided);
+    }
+
+    fn forget_buffer_target(&self, target: GLenum) {
+        self.buffers.borrow_mut().retain(|entry| entry.0 != target);
+    }
+
+    /// Records a binding that was made without asking the cache.
+    fn set_buffer(&self, target: GLenum, buffer: GLuint) {
+        self.forget_buffer_target(target);
+        self.buffers.borrow_mut().push((target, buffer));
+    }
+
+    fn forget(&self) {
+        self.program.set(None);
+        self.vertex_array.set(None);
+        self.buffers.borrow_mut().clear();
+        self.capabilities.borrow_mut().clear();
+        self.blend_func.set(None);
+        self.depth_func.set(None);
+        self.depth_mask.set(None);
+        self.viewport.set(None);
+    }
+}
+
+// -=-=-=-=-=-=-=-=-=-=-=-=-=-=-=-=-=-=-=-=-=-=-=-=-=-=-=-
+
+/// Cached setters. They skip the GL call when the context is known to be in
+/// the requested state already. State changed with the raw functions
+/// (`gl.UseProgram` etc.) is not seen by the cache, call `invalidate_state`
+/// afterwards.
+impl Gl {
+    pub fn use_program(&self, program: GLuint) {
+        if self
+            .state
+            .update(&self.state.program, program, |e| &mut e.use_program)
+        {
+            unsafe {
+                self.UseProgram(program);
+            }
+        }
+    }
+
+    pub fn bind_vertex_array(&self, vertex_array: GLuint) {
+        if self
+            .state
+            .update(&self.state.vertex_array, vertex_array, |e| {
+                &mut e.bind_vertex_array
+            })
+        {
+            unsafe {
+                self.BindVertexArray(vertex_array);
+            }
+            // the element array buffer binding is part of the vertex array
+            self.state.forget_buffer_target(super::ELEMENT_ARRAY_BUFFER);
+        }
+    }
+
+    pub fn bind_buffer(&self, target: GLenum, buffer: GLuint) {
+        if self
+            .state
+            .update_entry(&self.state.buffers, target, buffer, |e| &mut e.bind_buffer)
+        {
+            unsafe {
+                self.BindBuffer(target, buffer);
+            }
+        }
+    }
+
+    /// `glBindBufferBase`. Indexed bindings are not cached, so the call
+    /// always goes through, but it also binds the generic `target`.
+    pub fn bind_buffer_base(&self, target: GLenum, index: GLuint, buffer: GLuint) {
+        unsafe {
+            self.BindBufferBase(target, index, buffer);
+        }
+        self.state.set_buffer(target, buffer);
+    }
+
+    /// `glBindBufferRange`, see `bind_buffer_base`.
+    pub fn bind_buffer_range(
+        &self,
+        target: GLenum,
+        index: GLuint,
+        buffer: GLuint,
+        offset: GLintptr,
+        size: GLsizeiptr,
+    ) {
+        unsafe {
+            self.BindBufferRange(target, index, buffer, offset, size);
+        }
+        self.state.set_buffer(target, buffer);
+    }
+
+    pub fn enable(&self, capability: GLenum) {
+        self.set_capability(capability, true);
+    }
+
+    pub fn disable(&self, capability: GLenum) {
+        self.set_capability(capability, false);
+    }
+
+    fn set_capability(&self, capability: GLenum, enabled: bool) {
+        if self
+            .state
+            .update_entry(&self.state.capabilities, capability, enabled, |e| {
+                &mut e.enable_disable
+            })
+        {
+            unsafe {
+                if enabled {
+                    self.Enable(capability);
+                } else {
+                    self.Disable(capability);
+                }
+            }
+        }
+    }
+
+    pub fn blend_func(&self, source: GLenum, destination: GLenum) {
+        if self
+            .state
+            .update(&self.state.blend_func, (source, destination), |e| {
+                &mut e.blend_func
+            })
+        {
+            unsafe {
+                self.BlendFunc(source, destination);
+            }
+        }
+    }
+
+    pub fn depth_func(&self, func: GLenum) {
+        if self
+            .state
+            .update(&self.state.depth_func, func, |e| &mut e.depth_func)
+        {
+            unsafe {
+                self.DepthFunc(func);
+            }
+        }
+    }
+
+    pub fn depth_mask(&self, write: bool) {
+        if self
+            .state
+            .update(&self.state.depth_mask, write, |e| &mut e.depth_mask)
+        {
+            unsafe {
+                self.DepthMask(if write { super::TRUE } else { super::FALSE });
+            }
+        }
+    }
+
+    pub fn viewport(&self, x: GLint, y: GLint, width: GLsizei, height: GLsizei) {
+        if self
+            .state
+            .update(&self.state.viewport, (x, y, width, height), |e| {
+                &mut e.viewport
+            })
+        {
+            unsafe {
+                self.Viewport(x, y, width, height);
+            }
+        }
+    }
+
+    /// Deletes `program`. A new program may get the same name while the
+    /// deleted one is still in use, so the cached program is forgotten.
+    pub fn delete_program(&self, program: GLuint) {
+        unsafe {
+            self.DeleteProgram(program);
+        }
+        if self.state.program.get() == Some(program) {
+            self.state.program.set(None);
+        }
+    }
+
+    /// Deletes `vertex_array`, which reverts the binding to 0 if it was bound.
+    pub fn delete_vertex_array(&self, vertex_array: GLuint) {
+        unsafe {
+            self.DeleteVertexArrays(1, &vertex_array);
+        }
+        if self.state.vertex_array.get() == Some(vertex_array) {
+            self.state.vertex_array.set(Some(0));
+            self.state.forget_buffer_target(super::ELEMENT_ARRAY_BUFFER);
+        }
+    }
+
+    /// Deletes `buffer` and forgets the targets it was bound to.
+    pub fn delete_buffer(&self, buffer: GLuint) {
+        unsafe {
+            self.DeleteBuffers(1, &buffer);
+        }
+        self.state
+            .buffers
+            .borrow_mut()
+            .retain(|entry| entry.1 != buffer);
+    }
+
+    /// Forgets all cached state, e.g. after other code used the context.
+    pub fn invalidate_state(&self) {
+        self.state.forget();
+    }
+
+    pub fn elided_calls(&self) -> ElidedCalls {
+        self.state.elided.get()
+    }
+
+    pub fn reset_elided_calls(&self) {
+        self.state.elided.set(ElidedCalls::default());
+    }
+}
@@ -3,101 +3,524 @@ extern crate quote;
 extern crate syn;
 
 use proc_macro::TokenStream;
-use quote::quote;
+use quote::{quote, quote_spanned};
+use syn::spanned::Spanned;
 use syn::{parse_macro_input, DeriveInput};
 
-/// This generates an implementation of shader inputs like
+/// This generates an implementation of `crate::render_gl::Vertex` like
 ///
 /// ```
-/// impl Vertex {
-///     fn vertex_attrib_pointers(gl: &gl::Gl) {
-///         let stride = 6 * std::mem::size_of::<f32>();
+/// impl crate::render_gl::Vertex for Vertex {
+///     fn vertex_layout() -> Vec<crate::render_gl::VertexAttrib> {
+///         let mut layout = Vec::new();
 ///         let location = 0;
-///         let offset = 0;
-///         unsafe {
-///             data::f32_f32_f32::vertex_attrib_pointer(gl, location, stride, offset);
-///         }
-///         let location = 1;
-///         let offset = offset + std::mem::size_of::<data::f32_f32_f32>();
-///         unsafe {
-///             data::f32_f32_f32::vertex_attrib_pointer(gl, location, stride, offset);
-///         }
+///         let location = location;
+///         let offset = std::mem::offset_of!(Self, pos);
+///         layout.push(crate::render_gl::VertexAttrib {
+///             name: "pos",
+///             location,
+///             offset,
+///             format: <data::f32_f32_f32 as crate::render_gl::VertexAttribute>::FORMAT,
+///             divisor: 0,
+///         });
+///         let location = location + 1;
+///         ...
+///         layout
 ///     }
 /// }
+///
+/// const _: () = assert!(
+///     std::mem::size_of::<Vertex>()
+///         == 0 + <data::f32_f32_f32 as crate::render_gl::VertexAttribute>::FORMAT.size() + ...,
+///     "size of Vertex differs from the sum of its vertex attribute sizes",
+/// );
 /// ```
+///
+/// The struct needs `#[repr(C)]`, usually `#[repr(C, packed)]`. Offsets are
+/// taken from the struct, so padding between fields is fine without `packed`.
+/// The size check is only generated for packed structs without generics.
+///
+/// `Vertex::vertex_attrib_pointers` sets up the attribute pointers from this
+/// layout, and it can be checked against a linked program with
+/// `Program::check_vertex_layout`.
+///
+/// Fields without `#[location = N]` take the location after the previous
+/// field, starting at 0. Arrays of attribute types or of scalars such as
+/// `[f32; 4]` and nalgebra matrices such as `na::Matrix4<f32>` span one
+/// location per element or column. `#[flatten]` inserts the attributes of a
+/// nested vertex struct, with its locations counted from the one of the
+/// field.
+///
+/// `#[divisor = N]` on the struct or on a field makes the attributes advance
+/// once per `N` instances instead of once per vertex (`glVertexAttribDivisor`),
+/// for per-instance data such as `Mesh::set_instances`. A field attribute
+/// overrides the one of the struct.
 
-#[proc_macro_derive(VertexAttribPointers, attributes(location))]
+#[proc_macro_derive(VertexAttribPointers, attributes(location, divisor, flatten))]
 pub fn vertex_attrib_pointers_derive(input: TokenStream) -> TokenStream {
     let ast = parse_macro_input!(input as DeriveInput);
-    TokenStream::from(generate_impl(&ast))
+    TokenStream::from(generate_impl(&ast).unwrap_or_else(|e| e.to_compile_error()))
 }
 
-fn generate_struct_field_vertex_attrib_pointer_call(f: &syn::Field) -> quote::__rt::TokenStream {
-    let field_name = match f.ident {
+fn field_name(f: &syn::Field, index: usize) -> String {
+    match f.ident {
         Some(ref i) => format!("{}", i),
-        None => String::from(""),
+        None => format!("{}", index),
+    }
+}
+
+fn find_attr<'a>(attrs: &'a [syn::Attribute], name: &str) -> Option<&'a syn::Attribute> {
+    attrs.iter().find(|attr| attr.path.is_ident(name))
+}
+
+fn attr_usize(attrs: &[syn::Attribute], name: &str) -> syn::Result<Option<usize>> {
+    let attr = match find_attr(attrs, name) {
+        Some(attr) => attr,
+        None => return Ok(None),
     };
+    match attr.parse_meta()? {
+        syn::Meta::NameValue(syn::MetaNameValue {
+            lit: syn::Lit::Int(ref n),
+            ..
+        }) => n.base10_parse::<usize>().map(Some),
+        meta => Err(syn::Error::new_spanned(
+            meta,
+            format!("expected #[{} = <integer>]", name),
+        )),
+    }
+}
 
-    let location_val: usize = f
-        .attrs
-        .iter()
-        .find(|attr| {
-            attr.path
-                .segments
-                .first()
-                .map(|seg| "location" == seg.ident.to_string())
-                .unwrap_or(false)
-        })
-        .and_then(|attr| attr.parse_meta().ok())
-        .and_then(|meta| {
-            if let syn::Meta::NameValue(syn::MetaNameValue {
-                lit: syn::Lit::Int(n),
-                ..
-            }) = meta
-            {
-                n.base10_parse::<usize>().ok()
-            } else {
-                None
+fn attr_flag(attrs: &[syn::Attribute], name: &str) -> syn::Result<bool> {
+    let attr = match find_attr(attrs, name) {
+        Some(attr) => attr,
+        None => return Ok(false),
+    };
+    match attr.parse_meta()? {
+        syn::Meta::Path(_) => Ok(true),
+        meta => Err(syn::Error::new_spanned(
+            meta,
+            format!("expected #[{}] without arguments", name),
+        )),
+    }
+}
+
+fn field_divisor(f: &syn::Field, struct_divisor: u32) -> syn::Result<u32> {
+    Ok(attr_usize(&f.attrs, "divisor")?
+        .map(|d| d as u32)
+        .unwrap_or(struct_divisor))
+}
+
+/// How the attributes of a field are laid out.
+enum FieldKind {
+    /// one attribute of a `render_gl::data` type
+    Single,
+    /// `count` consecutive locations of `element`, one per array element or
+    /// matrix column, with the `VertexAttribute::FORMAT` of `element`
+    Repeated {
+        element: quote::__rt::TokenStream,
+        format: quote::__rt::TokenStream,
+        count: quote::__rt::TokenStream,
+    },
+    /// the attributes of a nested vertex struct
+    Flatten,
+}
+
+fn field_kind(f: &syn::Field) -> syn::Result<FieldKind> {
+    if attr_flag(&f.attrs, "flatten")? {
+        return Ok(FieldKind::Flatten);
+    }
+    match f.ty {
+        syn::Type::Array(ref array) => {
+            let len = &array.len;
+            let element = array_element(&array.elem)?;
+            Ok(FieldKind::Repeated {
+                format: quote_spanned!(array.elem.span()=>
+                    <#element as crate::render_gl::VertexAttribute>::FORMAT
+                ),
+                element,
+                count: quote!((#len)),
+            })
+        }
+        syn::Type::Path(ref path) => match matrix_shape(path)? {
+            Some((rows, columns, scalar)) => {
+                let name = vec![scalar.to_string(); rows].join("_");
+                let column = syn::Ident::new(&name, scalar.span());
+                let element = quote!(crate::render_gl::data::#column);
+                Ok(FieldKind::Repeated {
+                    format: quote!(<#element as crate::render_gl::VertexAttribute>::FORMAT),
+                    element,
+                    count: quote!(#columns),
+                })
             }
-        })
-        .unwrap_or_else(|| panic!("Field {} has no #[location = ?]", field_name));
+            None => Ok(FieldKind::Single),
+        },
+        _ => Ok(FieldKind::Single),
+    }
+}
+
+/// The attribute type of an array element; scalars stand for the one
+/// component types of `render_gl::data`, e.g. `f32` for `data::f32_`.
+fn array_element(elem: &syn::Type) -> syn::Result<quote::__rt::TokenStream> {
+    if let syn::Type::Array(_) = elem {
+        return Err(syn::Error::new_spanned(
+            elem,
+            "nested arrays are not supported as vertex attributes",
+        ));
+    }
+    if let syn::Type::Path(ref path) = elem {
+        if let Some(ident) = path.path.get_ident() {
+            let scalar = ident.to_string();
+            if SCALARS.contains(&scalar.as_str()) {
+                let data_type = syn::Ident::new(&format!("{}_", scalar), ident.span());
+                return Ok(quote!(crate::render_gl::data::#data_type));
+            }
+        }
+    }
+    Ok(quote!(#elem))
+}
+
+const SCALARS: &[&str] = &["f16", "f32", "f64", "i8", "u8", "i16", "u16", "i32", "u32"];
+
+/// (rows, columns, scalar) of a nalgebra `MatrixN<T>` or `MatrixRxC<T>`.
+fn matrix_shape(path: &syn::TypePath) -> syn::Result<Option<(usize, usize, syn::Ident)>> {
+    let segment = match path.path.segments.last() {
+        Some(segment) => segment,
+        None => return Ok(None),
+    };
+    let name = segment.ident.to_string();
+    let dims: Vec<usize> = match name.get("Matrix".len()..) {
+        Some(dims) if name.starts_with("Matrix") => {
+            match dims.split('x').map(str::parse).collect::<Result<_, _>>() {
+                Ok(dims) => dims,
+                Err(_) => return Ok(None),
+            }
+        }
+        _ => return Ok(None),
+    };
+    let (rows, columns) = match dims[..] {
+        [n] => (n, n),
+        [rows, columns] => (rows, columns),
+        _ => return Ok(None),
+    };
+    if !(2..=4).contains(&rows) || !(2..=4).contains(&columns) {
+        return Err(syn::Error::new_spanned(
+            &segment.ident,
+            "vertex attribute matrices have 2 to 4 rows and columns",
+        ));
+    }
+
+    let scalar = match segment.arguments {
+        syn::PathArguments::AngleBracketed(ref args) if args.args.len() == 1 => {
+            match args.args[0] {
+                syn::GenericArgument::Type(syn::Type::Path(ref scalar)) => {
+                    scalar.path.get_ident().cloned()
+                }
+                _ => None,
+            }
+        }
+        _ => None,
+    };
+    match scalar {
+        Some(ref scalar) if scalar == "f32" || scalar == "f64" => {
+            Ok(Some((rows, columns, scalar.clone())))
+        }
+        _ => Err(syn::Error::new_spanned(
+            &segment.arguments,
+            "vertex attribute matrices must be of f32 or f64",
+        )),
+    }
+}
 
+/// Returns the code adding the attributes of a field to `layout`, and the
+/// number of bytes GL reads for them.
+fn generate_struct_field_vertex_attribs(
+    f: &syn::Field,
+    index: usize,
+    struct_divisor: u32,
+) -> syn::Result<(quote::__rt::TokenStream, quote::__rt::TokenStream)> {
+    let field_name = field_name(f, index);
+    let member = match f.ident {
+        Some(ref ident) => syn::Member::Named(ident.clone()),
+        None => syn::Member::Unnamed(syn::Index::from(index)),
+    };
+    let location = match attr_usize(&f.attrs, "location")? {
+        Some(location) => quote!(#location),
+        None => quote!(location),
+    };
+    let divisor = field_divisor(f, struct_divisor)?;
     let field_type = &f.ty;
 
-    let result = quote!(
-      let location = #location_val;
-      unsafe {
-        #field_type::vertex_attrib_pointer(gl, location, stride, offset);
-      }
-      let offset = offset + std::mem::size_of::<#field_type>();
-    );
-    result.into()
-}
-
-fn generate_vertex_attrib_pointer_calls(body: &syn::Data) -> Vec<quote::__rt::TokenStream> {
-    match body {
-        syn::Data::Enum(_) => panic!("VertexAttribPointers cannot be implemented for enums"),
-        syn::Data::Union(_) => panic!("VertexAttribPointers cannot be implemented for unions"),
-        syn::Data::Struct(data) => data
-            .fields
-            .iter()
-            .map(generate_struct_field_vertex_attrib_pointer_call)
-            .collect(),
+    let (attribs, size) = match field_kind(f)? {
+        FieldKind::Single => {
+            // errors about a missing VertexAttribute impl point at the field
+            let format = quote_spanned!(field_type.span()=>
+                <#field_type as crate::render_gl::VertexAttribute>::FORMAT
+            );
+            (
+                quote!(
+                  layout.push(crate::render_gl::VertexAttrib {
+                    name: #field_name,
+                    location,
+                    offset,
+                    format: #format,
+                    divisor: #divisor,
+                  });
+                  let location = location + 1;
+                ),
+                quote!(#format.size()),
+            )
+        }
+        FieldKind::Repeated {
+            element,
+            format,
+            count,
+        } => (
+            quote!(
+              for i in 0..#count {
+                layout.push(crate::render_gl::VertexAttrib {
+                  name: #field_name,
+                  location: location + i,
+                  offset: offset + i * std::mem::size_of::<#element>(),
+                  format: #format,
+                  divisor: #divisor,
+                });
+              }
+              let location = location + #count;
+            ),
+            quote!(#count * #format.size()),
+        ),
+        FieldKind::Flatten => {
+            // the divisor of the nested struct applies unless one is set here
+            let nested_divisor = if divisor != 0 {
+                quote!(#divisor)
+            } else {
+                quote!(attrib.divisor)
+            };
+            let nested = quote_spanned!(field_type.span()=>
+                <#field_type as crate::render_gl::Vertex>::vertex_layout()
+            );
+            (
+                quote!(
+                  let nested = #nested;
+                  let count = nested.iter().map(|attrib| attrib.location + 1).max().unwrap_or(0);
+                  layout.extend(nested.into_iter().map(|attrib| crate::render_gl::VertexAttrib {
+                    location: location + attrib.location,
+                    offset: offset + attrib.offset,
+                    divisor: #nested_divisor,
+                    ..attrib
+                  }));
+                  let location = location + count;
+                ),
+                // checked by the derive of the nested struct
+                quote!(std::mem::size_of::<#field_type>()),
+            )
+        }
+    };
+
+    Ok((
+        quote!(
+          let location = #location;
+          let offset = std::mem::offset_of!(Self, #member);
+          #attribs
+        ),
+        size,
+    ))
+}
+
+/// Checks that the struct has `#[repr(C)]`, without which fields may be
+/// reordered, and returns whether it is also packed without padding.
+fn repr_packed(ast: &DeriveInput) -> syn::Result<bool> {
+    let mut c = false;
+    let mut packed = false;
+    for attr in ast.attrs.iter().filter(|attr| attr.path.is_ident("repr")) {
+        if let syn::Meta::List(list) = attr.parse_meta()? {
+            for nested in list.nested.iter() {
+                match nested {
+                    syn::NestedMeta::Meta(syn::Meta::Path(ref path)) if path.is_ident("C") => {
+                        c = true
+                    }
+                    syn::NestedMeta::Meta(syn::Meta::Path(ref path)) if path.is_ident("packed") => {
+                        packed = true
+                    }
+                    syn::NestedMeta::Meta(syn::Meta::List(ref list))
+                        if list.path.is_ident("packed") =>
+                    {
+                        packed = list.nested.len() == 1
+                            && match list.nested[0] {
+                                syn::NestedMeta::Lit(syn::Lit::Int(ref n)) => {
+                                    n.base10_digits() == "1"
+                                }
+                                _ => false,
+                            }
+                    }
+                    _ => {}
+                }
+            }
+        }
+    }
+    if c {
+        Ok(packed)
+    } else {
+        Err(syn::Error::new_spanned(
+            &ast.ident,
+            "VertexAttribPointers needs #[repr(C)] or #[repr(C, packed)], \
+             otherwise the fields may be reordered",
+        ))
     }
 }
 
-fn generate_impl(ast: &DeriveInput) -> quote::__rt::TokenStream {
+fn struct_fields<'a>(ast: &'a DeriveInput, derive: &str) -> syn::Result<&'a syn::Fields> {
+    let kind = match ast.data {
+        syn::Data::Struct(ref data) => return Ok(&data.fields),
+        syn::Data::Enum(_) => "enums",
+        syn::Data::Union(_) => "unions",
+    };
+    Err(syn::Error::new_spanned(
+        &ast.ident,
+        format!("{} cannot be derived for {}", derive, kind),
+    ))
+}
+
+fn generate_impl(ast: &DeriveInput) -> syn::Result<quote::__rt::TokenStream> {
     let ident = &ast.ident;
-    let generics = &ast.generics;
-    let where_clause = &ast.generics.where_clause;
-    let fields = generate_vertex_attrib_pointer_calls(&ast.data);
-    quote! {
-      impl #ident #generics #where_clause {
-          fn vertex_attrib_pointers(gl: &gl::Gl) {
-              let stride = std::mem::size_of::<Self>();
+    let (impl_generics, ty_generics, where_clause) = ast.generics.split_for_impl();
+    let packed = repr_packed(ast)?;
+    let divisor = attr_usize(&ast.attrs, "divisor")?.unwrap_or(0) as u32;
+    let (attribs, sizes): (Vec<_>, Vec<_>) = struct_fields(ast, "VertexAttribPointers")?
+        .iter()
+        .enumerate()
+        .map(|(i, f)| generate_struct_field_vertex_attribs(f, i, divisor))
+        .collect::<syn::Result<Vec<_>>>()?
+        .into_iter()
+        .unzip();
+
+    // A packed struct has no padding, so its size is what GL reads unless a
+    // field type does not match its FORMAT. Generic structs cannot be
+    // checked in a const item.
+    let size_check = if packed && ast.generics.params.is_empty() {
+        quote!(
+            const _: () = assert!(
+                std::mem::size_of::<#ident>() == 0 #(+ #sizes)*,
+                concat!(
+                    "size of ",
+                    stringify!(#ident),
+                    " differs from the sum of its vertex attribute sizes"
+                ),
+            );
+        )
+    } else {
+        quote!()
+    };
+
+    Ok(quote! {
+      impl #impl_generics crate::render_gl::Vertex for #ident #ty_generics #where_clause {
+          fn vertex_layout() -> Vec<crate::render_gl::VertexAttrib> {
+              let mut layout = Vec::new();
+              let location = 0;
+              #(#attribs)*
+              layout
+          }
+      }
+
+      #size_check
+    })
+}
+
+// -=-=-=-=-=-=-=-=-=-=-=-=-=-=-=-=-=-=-=-=-=-=-=-=-=-=-=-
+
+/// Implements `crate::render_gl::BufferBlock` for a struct whose fields all
+/// implement it, placing the fields like the members of a GLSL block:
+///
+/// ```
+/// impl BufferBlock for Camera {
+///     fn align(layout: BlockLayout) -> usize {
+///         let align = 1;
+///         let align = std::cmp::max(align, <na::Matrix4<f32> as BufferBlock>::align(layout));
+///         ...
+///         crate::render_gl::block::struct_align(layout, align)
+///     }
+///
+///     fn size(layout: BlockLayout) -> usize {
+///         let offset = 0;
+///         let offset = crate::render_gl::block::align_up(offset, <na::Matrix4<f32> as BufferBlock>::align(layout))
+///             + <na::Matrix4<f32> as BufferBlock>::size(layout);
+///         ...
+///         crate::render_gl::block::align_up(offset, Self::align(layout))
+///     }
+///
+///     fn write(&self, layout: BlockLayout, out: &mut Vec<u8>) {
+///         let start = out.len();
+///         crate::render_gl::block::pad(out, start, <na::Matrix4<f32> as BufferBlock>::align(layout));
+///         BufferBlock::write(&self.view, layout, out);
+///         ...
+///         crate::render_gl::block::pad(out, start, Self::align(layout));
+///     }
+/// }
+/// ```
+#[proc_macro_derive(BufferBlock)]
+pub fn buffer_block_derive(input: TokenStream) -> TokenStream {
+    let ast = parse_macro_input!(input as DeriveInput);
+    TokenStream::from(generate_buffer_block_impl(&ast).unwrap_or_else(|e| e.to_compile_error()))
+}
+
+fn generate_buffer_block_impl(ast: &DeriveInput) -> syn::Result<quote::__rt::TokenStream> {
+    let ident = &ast.ident;
+    let (impl_generics, ty_generics, where_clause) = ast.generics.split_for_impl();
+    let fields = struct_fields(ast, "BufferBlock")?;
+    let types: Vec<&syn::Type> = fields.iter().map(|f| &f.ty).collect();
+    let members: Vec<syn::Member> = fields
+        .iter()
+        .enumerate()
+        .map(|(i, f)| match f.ident {
+            Some(ref ident) => syn::Member::Named(ident.clone()),
+            None => syn::Member::Unnamed(syn::Index::from(i)),
+        })
+        .collect();
+    let types_2 = &types;
+
+    Ok(quote! {
+      impl #impl_generics crate::render_gl::BufferBlock for #ident #ty_generics #where_clause {
+          fn align(layout: crate::render_gl::BlockLayout) -> usize {
+              let align = 1;
+              #(
+                let align = std::cmp::max(
+                    align,
+                    <#types as crate::render_gl::BufferBlock>::align(layout),
+                );
+              )*
+              crate::render_gl::block::struct_align(layout, align)
+          }
+
+          fn size(layout: crate::render_gl::BlockLayout) -> usize {
               let offset = 0;
-              #(#fields)*
+              #(
+                let offset = crate::render_gl::block::align_up(
+                    offset,
+                    <#types as crate::render_gl::BufferBlock>::align(layout),
+                ) + <#types_2 as crate::render_gl::BufferBlock>::size(layout);
+              )*
+              crate::render_gl::block::align_up(
+                  offset,
+                  <Self as crate::render_gl::BufferBlock>::align(layout),
+              )
+          }
+
+          fn write(&self, layout: crate::render_gl::BlockLayout, out: &mut Vec<u8>) {
+              let start = out.len();
+              #(
+                crate::render_gl::block::pad(
+                    out,
+                    start,
+                    <#types as crate::render_gl::BufferBlock>::align(layout),
+                );
+                crate::render_gl::BufferBlock::write(&self.#members, layout, out);
+              )*
+              crate::render_gl::block::pad(
+                  out,
+                  start,
+                  <Self as crate::render_gl::BufferBlock>::align(layout),
+              );
           }
       }
-    }
+    })
 }
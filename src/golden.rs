@@ -0,0 +1,158 @@
+//! Golden-image tests: scenes are rendered into an offscreen framebuffer and
+//! compared with the references in `tests/golden`.
+//!
+//! On a mismatch the rendered image and a diff image (differing pixels in red)
+//! are written to `target/golden`. Run with `GOLDEN_UPDATE=1` to replace the
+//! references with the rendered images after an intended change, and with
+//! `GOLDEN_TOLERANCE=n` to override the allowed per-channel difference.
+//!
+//! The tests need a GL 3.3 context; on machines without a display run them
+//! with `SDL_VIDEODRIVER=offscreen` and Mesa's software rasterizer.
+
+use crate::headless::HeadlessContext;
+use crate::rectangle::Rectangle;
+use crate::render_gl::{ColorBuffer, Framebuffer, PixelFormat};
+use crate::resources::{Image, ImageColor, Resources};
+use crate::triangle::Triangle;
+use nalgebra as na;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+const WIDTH: u32 = 64;
+const HEIGHT: u32 = 64;
+
+/// Covers rounding differences between drivers.
+const DEFAULT_TOLERANCE: u8 = 2;
+
+/// SDL can only be initialized once at a time, tests run on several threads.
+static GL_LOCK: Mutex<()> = Mutex::new(());
+
+/// Renders one frame with `draw` into a `WIDTH` x `HEIGHT` framebuffer that is
+/// cleared with the same color as the window.
+fn render<F>(draw: F) -> Image
+where
+    F: FnOnce(&gl::Gl, &Resources),
+{
+    let _lock = GL_LOCK
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner());
+    let context = HeadlessContext::new(false).expect("failed to create GL context");
+    let gl = &context.gl;
+    let res = Resources::from_path(&Path::new(env!("CARGO_MANIFEST_DIR")).join("assets"));
+
+    let framebuffer = Framebuffer::with_color_and_depth(gl, WIDTH, HEIGHT, PixelFormat::Rgba8)
+        .expect("failed to create framebuffer");
+    framebuffer.clear(&ColorBuffer::from_color(na::Vector3::new(0.3, 0.3, 0.5)));
+    draw(gl, &res);
+    framebuffer.read_pixels()
+}
+
+fn golden_dir() -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/golden")
+}
+
+fn output_dir() -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR")).join("target/golden")
+}
+
+fn tolerance() -> u8 {
+    std::env::var("GOLDEN_TOLERANCE")
+        .ok()
+        .and_then(|t| t.parse().ok())
+        .unwrap_or(DEFAULT_TOLERANCE)
+}
+
+struct Comparison {
+    differing_pixels: usize,
+    max_difference: u8,
+    diff: Image,
+}
+
+/// Compares two RGBA images channel by channel. The diff image shows
+/// differing pixels in red on top of a dimmed copy of `expected`.
+fn compare(actual: &Image, expected: &Image, tolerance: u8) -> Comparison {
+    let mut differing_pixels = 0;
+    let mut max_difference = 0;
+    let mut diff = Vec::with_capacity(expected.data.len());
+    for (a, e) in actual.data.chunks(4).zip(expected.data.chunks(4)) {
+        let difference = a
+            .iter()
+            .zip(e)
+            .map(|(&a, &e)| a.max(e) - a.min(e))
+            .max()
+            .unwrap_or(0);
+        max_difference = max_difference.max(difference);
+        if difference > tolerance {
+            differing_pixels += 1;
+            diff.extend_from_slice(&[255, 0, 0, 255]);
+        } else {
+            diff.extend(e[..3].iter().map(|c| c / 4));
+            diff.push(255);
+        }
+    }
+    Comparison {
+        differing_pixels,
+        max_difference,
+        diff: Image {
+            width: expected.width,
+            height: expected.height,
+            color: ImageColor::Rgba,
+            data: diff,
+            path: PathBuf::new(),
+        },
+    }
+}
+
+fn assert_matches_golden(name: &str, actual: &Image) {
+    let reference = golden_dir().join(format!("{}.png", name));
+    if std::env::var_os("GOLDEN_UPDATE").is_some() {
+        actual.save_png(&reference).unwrap();
+        return;
+    }
+
+    let expected = Resources::from_path(&golden_dir())
+        .load_image(&format!("{}.png", name))
+        .unwrap_or_else(|e| panic!("failed to load {}: {}", reference.display(), e));
+    assert_eq!(expected.color, ImageColor::Rgba, "{} is not RGBA", name);
+    assert_eq!(
+        (actual.width, actual.height),
+        (expected.width, expected.height),
+        "{} has a different size than the reference",
+        name
+    );
+
+    let comparison = compare(actual, &expected, tolerance());
+    if comparison.differing_pixels > 0 {
+        let dir = output_dir();
+        std::fs::create_dir_all(&dir).unwrap();
+        let actual_path = dir.join(format!("{}.actual.png", name));
+        let diff_path = dir.join(format!("{}.diff.png", name));
+        actual.save_png(&actual_path).unwrap();
+        comparison.diff.save_png(&diff_path).unwrap();
+        panic!(
+            "{}: {} pixels differ by up to {} (tolerance {}), see {} and {}",
+            name,
+            comparison.differing_pixels,
+            comparison.max_difference,
+            tolerance(),
+            actual_path.display(),
+            diff_path.display()
+        );
+    }
+}
+
+#[test]
+fn triangle() {
+    let image = render(|gl, res| {
+        Triangle::new(res, gl).unwrap().render(gl);
+    });
+    assert_matches_golden("triangle", &image);
+}
+
+#[test]
+fn rectangle() {
+    let image = render(|gl, res| {
+        Rectangle::new(res, gl).unwrap().render(gl);
+    });
+    assert_matches_golden("rectangle", &image);
+}
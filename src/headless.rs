@@ -0,0 +1,157 @@
+use crate::rectangle::Rectangle;
+use crate::render_gl::{ColorBuffer, Framebuffer, PixelFormat};
+use crate::resources::Resources;
+use nalgebra as na;
+use std::path::{Path, PathBuf};
+
+pub const USAGE: &str = "\
+usage: opengl [--headless [--frames N] [--size WIDTHxHEIGHT] [--output DIR] [--software]]
+
+  --headless   render offscreen and write every frame as PNG instead of opening a window
+  --frames     number of frames to render, default 1
+  --size       framebuffer size, default 800x600
+  --output     directory for frame_NNNN.png, default the current directory
+  --software   force Mesa's software rasterizer (LIBGL_ALWAYS_SOFTWARE=1)";
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Options {
+    pub frames: usize,
+    pub width: u32,
+    pub height: u32,
+    pub output: PathBuf,
+    pub software: bool,
+}
+
+impl Default for Options {
+    fn default() -> Self {
+        Options {
+            frames: 1,
+            width: 800,
+            height: 600,
+            output: PathBuf::from("."),
+            software: false,
+        }
+    }
+}
+
+impl Options {
+    /// Parses the command line without the program name. Returns `None` when
+    /// `--headless` is not given, i.e. the window should be opened.
+    pub fn from_args<I: IntoIterator<Item = String>>(
+        args: I,
+    ) -> Result<Option<Options>, failure::Error> {
+        let mut headless = false;
+        let mut options = Options::default();
+        let mut args = args.into_iter();
+        while let Some(arg) = args.next() {
+            let mut value = || {
+                args.next()
+                    .ok_or_else(|| failure::format_err!("{} needs a value", arg))
+            };
+            match arg.as_str() {
+                "--headless" => headless = true,
+                "--software" => options.software = true,
+                "--frames" => {
+                    options.frames = value()?
+                        .parse()
+                        .map_err(|_| failure::format_err!("--frames needs a number"))?
+                }
+                "--size" => {
+                    let size = value()?;
+                    let (width, height) = parse_size(&size)
+                        .ok_or_else(|| failure::format_err!("invalid --size {}", size))?;
+                    options.width = width;
+                    options.height = height;
+                }
+                "--output" => options.output = PathBuf::from(value()?),
+                _ => return Err(failure::format_err!("unknown argument {}", arg)),
+            }
+        }
+        if !headless && options != Options::default() {
+            return Err(failure::err_msg("options need --headless"));
+        }
+        Ok(if headless { Some(options) } else { None })
+    }
+}
+
+fn parse_size(size: &str) -> Option<(u32, u32)> {
+    let x = size.find('x')?;
+    let width = size[..x].parse().ok()?;
+    let height = size[x + 1..].parse().ok()?;
+    if width == 0 || height == 0 {
+        return None;
+    }
+    Some((width, height))
+}
+
+// -=-=-=-=-=-=-=-=-=-=-=-=-=-=-=-=-=-=-=-=-=-=-=-=-=-=-=-
+
+/// GL 3.3 core context without a visible window. Rendering goes into a
+/// `Framebuffer`, the hidden window only exists to own the context.
+///
+/// On machines without a display, run with `SDL_VIDEODRIVER=offscreen` so SDL
+/// creates the context through EGL.
+pub struct HeadlessContext {
+    pub gl: gl::Gl,
+    _gl_context: sdl2::video::GLContext,
+    _window: sdl2::video::Window,
+    _video: sdl2::VideoSubsystem,
+    _sdl: sdl2::Sdl,
+}
+
+impl HeadlessContext {
+    pub fn new(software: bool) -> Result<HeadlessContext, failure::Error> {
+        if software {
+            // has to be set before the driver is loaded
+            std::env::set_var("LIBGL_ALWAYS_SOFTWARE", "1");
+        }
+
+        let sdl = sdl2::init().map_err(failure::err_msg)?;
+        let video = sdl.video().map_err(failure::err_msg)?;
+        let gl_attr = video.gl_attr();
+        gl_attr.set_context_profile(sdl2::video::GLProfile::Core);
+        gl_attr.set_context_version(3, 3);
+
+        let window = video
+            .window("opengl headless", 1, 1)
+            .opengl()
+            .hidden()
+            .build()
+            .map_err(failure::err_msg)?;
+        let gl_context = window.gl_create_context().map_err(failure::err_msg)?;
+        let gl = gl::Gl::load_with(|s| video.gl_get_proc_address(s) as *const std::os::raw::c_void);
+
+        Ok(HeadlessContext {
+            gl,
+            _gl_context: gl_context,
+            _window: window,
+            _video: video,
+            _sdl: sdl,
+        })
+    }
+}
+
+/// Renders `options.frames` frames of the same scene as the window and writes
+/// them to `options.output`.
+pub fn run(options: &Options) -> Result<(), failure::Error> {
+    let context = HeadlessContext::new(options.software)?;
+    let gl = &context.gl;
+
+    let resources = Resources::from_relative_exe_path(Path::new("assets"))?;
+    let rectangle = Rectangle::new(&resources, gl)?;
+
+    let framebuffer =
+        Framebuffer::with_color_and_depth(gl, options.width, options.height, PixelFormat::Rgba8)?;
+    let color_buffer = ColorBuffer::from_color(na::Vector3::new(0.3, 0.3, 0.5));
+
+    std::fs::create_dir_all(&options.output)?;
+    for frame in 0..options.frames {
+        framebuffer.clear(&color_buffer);
+        rectangle.render(gl);
+
+        let path = options.output.join(format!("frame_{:04}.png", frame));
+        framebuffer.read_pixels().save_png(&path)?;
+        println!("wrote {}", path.display());
+    }
+    Ok(())
+}
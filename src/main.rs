@@ -1,4 +1,7 @@
 mod debug;
+#[cfg(test)]
+mod golden;
+mod headless;
 mod rectangle;
 pub mod render_gl;
 pub mod resources;
@@ -101,8 +104,16 @@ fn run(state: State) -> Result<(), failure::Error> {
                        match watcher.rx.try_recv() {
                            Ok(evt) => {
                                println!("{:#?}", evt);
-                               triangle.reload(&gl, &resources)?;
-                               rectangle.reload(&gl, &resources)?;
+                               match evt.path {
+                                   Some(ref path) => {
+                                       triangle.reload_changed(&gl, &resources, path)?;
+                                       rectangle.reload_changed(&gl, &resources, path)?;
+                                   }
+                                   None => {
+                                       triangle.reload(&gl, &resources)?;
+                                       rectangle.reload(&gl, &resources)?;
+                                   }
+                               }
                            }
                            Err(_) => {}
                        }
@@ -134,7 +145,18 @@ fn run(state: State) -> Result<(), failure::Error> {
 }
 
 fn main() {
-    if let Err(e) = setup().and_then(run) {
+    let options = match headless::Options::from_args(std::env::args().skip(1)) {
+        Ok(options) => options,
+        Err(e) => {
+            println!("{}\n{}", e, headless::USAGE);
+            std::process::exit(2);
+        }
+    };
+    let result = match options {
+        Some(options) => headless::run(&options),
+        None => setup().and_then(run),
+    };
+    if let Err(e) = result {
         println!("Error occurred: {}", failure_to_string(e));
         std::process::exit(1);
     }
@@ -1,8 +1,6 @@
 use crate::render_gl;
-use crate::render_gl::buffer;
-use crate::render_gl::buffer::{ArrayBuffer, ElementArrayBuffer, VertexArray};
 use crate::render_gl::data;
-use crate::render_gl::Program;
+use crate::render_gl::{Mesh, Program, Topology};
 use crate::resources::{Reloadable, Resources};
 use failure;
 use gl;
@@ -19,9 +17,7 @@ struct Vertex {
 
 pub struct Rectangle {
     program: render_gl::Program,
-    vao: buffer::VertexArray,
-    _vbo: buffer::ArrayBuffer,
-    _ebo: buffer::ElementArrayBuffer,
+    mesh: Mesh<Vertex>,
 }
 
 impl Rectangle {
@@ -46,43 +42,16 @@ impl Rectangle {
         ];
         let indices: Vec<u32> = vec![0, 1, 2, 2, 3, 0];
 
-        let vao = VertexArray::new(gl);
-        let buffer = ArrayBuffer::new(gl);
-        let element_buffer = ElementArrayBuffer::new(gl);
+        let mesh = Mesh::indexed(gl, &vertices, &indices, Topology::TriangleStrip);
 
-        vao.bind();
+        let program = Program::from_res(&gl, &res, "shaders/triangle")?;
+        mesh.check_program(&program)?;
 
-        buffer.bind();
-        buffer.static_draw(&vertices);
-        Vertex::vertex_attrib_pointers(&gl);
-
-        element_buffer.bind();
-        element_buffer.static_draw(&indices);
-
-        vao.unbind();
-
-        buffer.unbind();
-        element_buffer.unbind();
-
-        Ok(Rectangle {
-            program: Program::from_res(&gl, &res, "shaders/triangle")?,
-            vao,
-            _vbo: buffer,
-            _ebo: element_buffer,
-        })
+        Ok(Rectangle { program, mesh })
     }
 
-    pub fn render(&self, gl: &gl::Gl) {
-        self.program.set_used();
-        self.vao.bind();
-        unsafe {
-            gl.DrawElements(
-                gl::TRIANGLE_STRIP,
-                6,
-                gl::UNSIGNED_INT,
-                0 as *const gl::types::GLvoid,
-            );
-        }
+    pub fn render(&self, _gl: &gl::Gl) {
+        self.mesh.draw(&self.program);
     }
 }
 
@@ -94,6 +63,10 @@ impl Reloadable for Rectangle {
     fn reload(&mut self, gl: &gl::Gl, res: &Resources) -> Result<(), failure::Error> {
         println!("reloading rectangle");
         Program::from_res(&gl, &res, "shaders/triangle")
+            .and_then(|program| {
+                self.mesh.check_program(&program)?;
+                Ok(program)
+            })
             .map(|program| self.program = program)
             .unwrap_or_else(|err| {
                 println!("Failed to reload rectangle. {:?}", err);
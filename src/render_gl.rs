@@ -1,10 +1,38 @@
 mod shader;
 mod viewport;
 mod color_buffer;
+mod capabilities;
+mod diagnostics;
+mod framebuffer;
+mod mesh;
+mod preprocessor;
+mod program_binary_cache;
+mod program_cache;
+mod program_interface;
+mod ring_buffer;
+mod texture;
+mod uniform;
+mod vertex;
 
+pub mod block;
 pub mod buffer;
 pub mod data;
 
-pub use self::shader::{Error, Program, Shader};
+pub use self::shader::{ComputeProgram, Error, Program, Shader, ShaderSource};
 pub use self::viewport::Viewport;
 pub use self::color_buffer::ColorBuffer;
+pub use self::block::{BlockLayout, BufferBlock};
+pub use self::diagnostics::{Diagnostic, Diagnostics, Severity};
+pub use self::framebuffer::{
+    DepthAttachment, Error as FramebufferError, Framebuffer, Renderbuffer,
+};
+pub use self::mesh::{Mesh, Topology};
+pub use self::program_binary_cache::{ProgramBinaryCache, ProgramBinaryKey};
+pub use self::program_cache::ProgramCache;
+pub use self::program_interface::{ActiveVariable, ProgramInterface};
+pub use self::ring_buffer::{Error as RingBufferError, RingBuffer};
+pub use self::texture::{CubeFace, Filter, PixelFormat, Texture, TextureKind, TextureParams, Wrap};
+pub use self::uniform::{Sampler, Uniform};
+pub use self::vertex::{
+    AttribBaseType, AttribFormat, LayoutMismatch, Vertex, VertexAttrib, VertexAttribute,
+};
@@ -0,0 +1,298 @@
+use nalgebra as na;
+
+/// Memory layout of a uniform or shader storage block, see section 7.6.2.2
+/// of the GL 4.5 spec. `Std430` is only allowed for shader storage blocks.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum BlockLayout {
+    Std140,
+    Std430,
+}
+
+/// A value that can be written to a uniform or shader storage buffer in
+/// std140 or std430 layout. Implemented for scalars, vectors, matrices and
+/// arrays; `#[derive(BufferBlock)]` implements it for structs of those.
+pub trait BufferBlock {
+    /// Base alignment in bytes.
+    fn align(layout: BlockLayout) -> usize;
+
+    /// Size in bytes, for structs including the padding at the end.
+    fn size(layout: BlockLayout) -> usize;
+
+    /// Appends the value to `out`, which the caller has padded to `align`.
+    fn write(&self, layout: BlockLayout, out: &mut Vec<u8>);
+
+    fn to_std140(&self) -> Vec<u8> {
+        let mut out = Vec::with_capacity(Self::size(BlockLayout::Std140));
+        self.write(BlockLayout::Std140, &mut out);
+        out
+    }
+
+    fn to_std430(&self) -> Vec<u8> {
+        let mut out = Vec::with_capacity(Self::size(BlockLayout::Std430));
+        self.write(BlockLayout::Std430, &mut out);
+        out
+    }
+}
+
+pub fn align_up(offset: usize, align: usize) -> usize {
+    offset + (align - offset % align) % align
+}
+
+/// Pads `out` with zeros so that the next value starts at a multiple of
+/// `align` counted from `start`.
+pub fn pad(out: &mut Vec<u8>, start: usize, align: usize) {
+    let len = start + align_up(out.len() - start, align);
+    out.resize(len, 0);
+}
+
+/// Base alignment of a struct with members aligned to at most
+/// `max_member_align`. std140 rounds it up to the alignment of a vec4.
+pub fn struct_align(layout: BlockLayout, max_member_align: usize) -> usize {
+    match layout {
+        BlockLayout::Std140 => align_up(max_member_align, 16),
+        BlockLayout::Std430 => max_member_align,
+    }
+}
+
+// -=-=-=-=-=-=-=-=-=-=-=-=-=-=-=-=-=-=-=-=-=-=-=-=-=-=-=-
+
+macro_rules! scalar_block {
+    ($t:ty) => {
+        impl BufferBlock for $t {
+            fn align(_layout: BlockLayout) -> usize {
+                4
+            }
+
+            fn size(_layout: BlockLayout) -> usize {
+                4
+            }
+
+            fn write(&self, _layout: BlockLayout, out: &mut Vec<u8>) {
+                out.extend_from_slice(&self.to_ne_bytes());
+            }
+        }
+    };
+}
+
+scalar_block!(f32);
+scalar_block!(i32);
+scalar_block!(u32);
+
+/// GLSL `bool` is 4 bytes in a block.
+impl BufferBlock for bool {
+    fn align(_layout: BlockLayout) -> usize {
+        4
+    }
+
+    fn size(_layout: BlockLayout) -> usize {
+        4
+    }
+
+    fn write(&self, layout: BlockLayout, out: &mut Vec<u8>) {
+        (*self as u32).write(layout, out);
+    }
+}
+
+macro_rules! vector_block {
+    ($t:ty, $align:expr, $size:expr) => {
+        impl BufferBlock for $t {
+            fn align(_layout: BlockLayout) -> usize {
+                $align
+            }
+
+            fn size(_layout: BlockLayout) -> usize {
+                $size
+            }
+
+            fn write(&self, layout: BlockLayout, out: &mut Vec<u8>) {
+                for c in self.iter() {
+                    c.write(layout, out);
+                }
+            }
+        }
+    };
+}
+
+vector_block!(na::Vector2<f32>, 8, 8);
+vector_block!(na::Vector3<f32>, 16, 12);
+vector_block!(na::Vector4<f32>, 16, 16);
+vector_block!(na::Vector2<i32>, 8, 8);
+vector_block!(na::Vector3<i32>, 16, 12);
+vector_block!(na::Vector4<i32>, 16, 16);
+vector_block!(na::Vector2<u32>, 8, 8);
+vector_block!(na::Vector3<u32>, 16, 12);
+vector_block!(na::Vector4<u32>, 16, 16);
+
+/// Column-major matrices are laid out like an array of their column vectors.
+macro_rules! matrix_block {
+    ($t:ty, $column:ty, $columns:expr) => {
+        impl BufferBlock for $t {
+            fn align(layout: BlockLayout) -> usize {
+                <[$column; $columns]>::align(layout)
+            }
+
+            fn size(layout: BlockLayout) -> usize {
+                <[$column; $columns]>::size(layout)
+            }
+
+            fn write(&self, layout: BlockLayout, out: &mut Vec<u8>) {
+                let start = out.len();
+                let stride = array_stride::<$column>(layout);
+                for column in self.column_iter() {
+                    pad(out, start, stride);
+                    column.into_owned().write(layout, out);
+                }
+                pad(out, start, stride);
+            }
+        }
+    };
+}
+
+matrix_block!(na::Matrix2<f32>, na::Vector2<f32>, 2);
+matrix_block!(na::Matrix3<f32>, na::Vector3<f32>, 3);
+matrix_block!(na::Matrix4<f32>, na::Vector4<f32>, 4);
+
+/// std140 rounds the alignment of arrays up to the alignment of a vec4.
+fn array_align<T: BufferBlock>(layout: BlockLayout) -> usize {
+    match layout {
+        BlockLayout::Std140 => align_up(T::align(layout), 16),
+        BlockLayout::Std430 => T::align(layout),
+    }
+}
+
+/// Distance between array elements, e.g. 16 bytes for a std140 `float[]`.
+fn array_stride<T: BufferBlock>(layout: BlockLayout) -> usize {
+    align_up(T::size(layout), array_align::<T>(layout))
+}
+
+impl<T: BufferBlock, const N: usize> BufferBlock for [T; N] {
+    fn align(layout: BlockLayout) -> usize {
+        array_align::<T>(layout)
+    }
+
+    fn size(layout: BlockLayout) -> usize {
+        N * array_stride::<T>(layout)
+    }
+
+    fn write(&self, layout: BlockLayout, out: &mut Vec<u8>) {
+        let start = out.len();
+        let stride = array_stride::<T>(layout);
+        for element in self.iter() {
+            pad(out, start, stride);
+            element.write(layout, out);
+        }
+        pad(out, start, stride);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use render_gl_derive::BufferBlock;
+
+    fn f32_at(bytes: &[u8], offset: usize) -> f32 {
+        let mut b = [0; 4];
+        b.copy_from_slice(&bytes[offset..offset + 4]);
+        f32::from_ne_bytes(b)
+    }
+
+    #[derive(BufferBlock)]
+    struct Vec3Float {
+        v: na::Vector3<f32>,
+        f: f32,
+    }
+
+    #[test]
+    fn float_fills_vec3_padding() {
+        let block = Vec3Float {
+            v: na::Vector3::new(1.0, 2.0, 3.0),
+            f: 4.0,
+        };
+        for &layout in &[BlockLayout::Std140, BlockLayout::Std430] {
+            assert_eq!(Vec3Float::size(layout), 16);
+            let bytes = match layout {
+                BlockLayout::Std140 => block.to_std140(),
+                BlockLayout::Std430 => block.to_std430(),
+            };
+            assert_eq!(bytes.len(), 16);
+            assert_eq!(f32_at(&bytes, 8), 3.0);
+            assert_eq!(f32_at(&bytes, 12), 4.0);
+        }
+    }
+
+    #[test]
+    fn float_array_stride() {
+        let array = [1.0f32, 2.0, 3.0];
+        assert_eq!(<[f32; 3]>::align(BlockLayout::Std140), 16);
+        assert_eq!(<[f32; 3]>::size(BlockLayout::Std140), 48);
+        let bytes = array.to_std140();
+        assert_eq!(bytes.len(), 48);
+        assert_eq!(f32_at(&bytes, 16), 2.0);
+        assert_eq!(f32_at(&bytes, 32), 3.0);
+
+        assert_eq!(<[f32; 3]>::align(BlockLayout::Std430), 4);
+        assert_eq!(<[f32; 3]>::size(BlockLayout::Std430), 12);
+        let bytes = array.to_std430();
+        assert_eq!(bytes.len(), 12);
+        assert_eq!(f32_at(&bytes, 4), 2.0);
+        assert_eq!(f32_at(&bytes, 8), 3.0);
+    }
+
+    #[test]
+    fn matrix_column_stride() {
+        // columns 1..3, 4..6, 7..9
+        let mat3 = na::Matrix3::new(1.0, 4.0, 7.0, 2.0, 5.0, 8.0, 3.0, 6.0, 9.0);
+        for bytes in &[mat3.to_std140(), mat3.to_std430()] {
+            assert_eq!(bytes.len(), 48);
+            assert_eq!(f32_at(bytes, 0), 1.0);
+            assert_eq!(f32_at(bytes, 16), 4.0);
+            assert_eq!(f32_at(bytes, 32), 7.0);
+            assert_eq!(f32_at(bytes, 40), 9.0);
+        }
+
+        // a vec2 column is padded to a vec4 in std140 only
+        let mat2 = na::Matrix2::new(1.0, 3.0, 2.0, 4.0);
+        let bytes = mat2.to_std140();
+        assert_eq!(bytes.len(), 32);
+        assert_eq!(f32_at(&bytes, 16), 3.0);
+        let bytes = mat2.to_std430();
+        assert_eq!(bytes.len(), 16);
+        assert_eq!(f32_at(&bytes, 8), 3.0);
+    }
+
+    #[derive(BufferBlock)]
+    struct Inner {
+        x: f32,
+    }
+
+    #[derive(BufferBlock)]
+    struct Outer {
+        a: f32,
+        inner: Inner,
+        b: f32,
+    }
+
+    #[test]
+    fn nested_struct_alignment() {
+        let block = Outer {
+            a: 1.0,
+            inner: Inner { x: 2.0 },
+            b: 3.0,
+        };
+
+        assert_eq!(Inner::align(BlockLayout::Std140), 16);
+        assert_eq!(Inner::size(BlockLayout::Std140), 16);
+        assert_eq!(Outer::size(BlockLayout::Std140), 48);
+        let bytes = block.to_std140();
+        assert_eq!(bytes.len(), 48);
+        assert_eq!(f32_at(&bytes, 16), 2.0);
+        assert_eq!(f32_at(&bytes, 32), 3.0);
+
+        assert_eq!(Inner::align(BlockLayout::Std430), 4);
+        assert_eq!(Outer::size(BlockLayout::Std430), 12);
+        let bytes = block.to_std430();
+        assert_eq!(bytes.len(), 12);
+        assert_eq!(f32_at(&bytes, 4), 2.0);
+        assert_eq!(f32_at(&bytes, 8), 3.0);
+    }
+}
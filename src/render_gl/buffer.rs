@@ -1,4 +1,7 @@
+use super::vertex::Vertex;
 use gl;
+use std::any::TypeId;
+use std::cell::{Cell, RefCell};
 
 pub trait BufferType {
     const BUFFER_TYPE: gl::types::GLuint;
@@ -14,17 +17,78 @@ impl BufferType for BufferTypeElementArray {
     const BUFFER_TYPE: gl::types::GLuint = gl::ELEMENT_ARRAY_BUFFER;
 }
 
+pub struct BufferTypeUniform;
+impl BufferType for BufferTypeUniform {
+    const BUFFER_TYPE: gl::types::GLuint = gl::UNIFORM_BUFFER;
+}
+impl IndexedBufferType for BufferTypeUniform {}
+
+pub struct BufferTypeShaderStorage;
+impl BufferType for BufferTypeShaderStorage {
+    const BUFFER_TYPE: gl::types::GLuint = gl::SHADER_STORAGE_BUFFER;
+}
+impl IndexedBufferType for BufferTypeShaderStorage {}
+
+/// Buffer targets with indexed binding points that blocks in programs refer
+/// to.
+pub trait IndexedBufferType: BufferType {}
+
 pub type ArrayBuffer = Buffer<BufferTypeArray>;
 pub type ElementArrayBuffer = Buffer<BufferTypeElementArray>;
+pub type UniformBuffer = Buffer<BufferTypeUniform>;
+/// Needs GL 4.3.
+pub type ShaderStorageBuffer = Buffer<BufferTypeShaderStorage>;
 
 // -=-=-=-=-=-=-=-=-=-=-=-=-=-=-=-=-=-=-=-=-=-=-=-=-=-=-=-
 
+/// Type of the elements of a `Buffer`, recorded when the buffer is
+/// (re)allocated so that lengths are counted in elements of that type.
+#[derive(Debug, Copy, Clone)]
+struct ElementType {
+    id: TypeId,
+    name: &'static str,
+    size: usize,
+}
+
+impl ElementType {
+    fn of<T: 'static>() -> ElementType {
+        let size = std::mem::size_of::<T>();
+        assert!(
+            size > 0,
+            "zero-sized type {} can not be stored in a buffer",
+            std::any::type_name::<T>()
+        );
+        ElementType {
+            id: TypeId::of::<T>(),
+            name: std::any::type_name::<T>(),
+            size,
+        }
+    }
+}
+
+/// Buffer object. Uploads and mapping do not need the buffer to be bound:
+/// they use direct state access when the context supports it (GL 4.5 or
+/// ARB_direct_state_access), and `GL_COPY_WRITE_BUFFER` otherwise, which
+/// leaves the other bindings and the bound vertex array alone.
+///
+/// The functions that allocate the buffer (`static_draw`, `dynamic_draw`,
+/// `stream_draw`, `reserve` and `storage`) record the element type, `len`
+/// and `capacity` count elements of it. The others (`sub_data`, `update`
+/// and `map`) panic when used with a different type, all of them panic for
+/// a zero-sized type. After `storage` the buffer can not be reallocated.
 pub struct Buffer<B>
 where
     B: BufferType,
 {
     vbo: gl::types::GLuint,
     gl: gl::Gl,
+    dsa: bool,
+    element: Cell<Option<ElementType>>,
+    /// allocated with `storage`
+    immutable: Cell<bool>,
+    // in bytes
+    len: Cell<usize>,
+    capacity: Cell<usize>,
     _marker: ::std::marker::PhantomData<B>,
 }
 
@@ -33,44 +97,263 @@ where
     B: BufferType,
 {
     pub fn new(gl: &gl::Gl) -> Self {
+        let dsa = gl.has_direct_state_access();
         let mut vbo: gl::types::GLuint = 0;
         unsafe {
-            gl.GenBuffers(1, &mut vbo);
+            if dsa {
+                gl.CreateBuffers(1, &mut vbo);
+            } else {
+                gl.GenBuffers(1, &mut vbo);
+            }
         };
         Buffer {
             vbo,
             gl: gl.clone(),
+            dsa,
+            element: Cell::new(None),
+            immutable: Cell::new(false),
+            len: Cell::new(0),
+            capacity: Cell::new(0),
             _marker: ::std::marker::PhantomData,
         }
     }
 
     pub fn bind(&self) {
-        unsafe {
-            self.gl.BindBuffer(B::BUFFER_TYPE, self.vbo);
-        };
+        self.gl.bind_buffer(B::BUFFER_TYPE, self.vbo);
     }
 
     pub fn unbind(&self) {
-        unsafe {
-            self.gl.BindBuffer(B::BUFFER_TYPE, 0);
-        };
+        self.gl.bind_buffer(B::BUFFER_TYPE, 0);
+    }
+
+    /// Number of elements written to the buffer.
+    pub fn len(&self) -> usize {
+        self.element
+            .get()
+            .map_or(0, |element| self.len.get() / element.size)
+    }
+
+    /// Number of elements the buffer can hold without reallocating.
+    pub fn capacity(&self) -> usize {
+        self.element
+            .get()
+            .map_or(0, |element| self.capacity.get() / element.size)
     }
 
-    pub fn static_draw<T>(&self, data: &[T]) {
+    /// Name of the element type, `None` before the buffer is allocated.
+    pub fn element_type(&self) -> Option<&'static str> {
+        self.element.get().map(|element| element.name)
+    }
+
+    fn set_element<T: 'static>(&self) {
+        self.element.set(Some(ElementType::of::<T>()));
+    }
+
+    fn check_mutable(&self) {
+        assert!(
+            !self.immutable.get(),
+            "buffer has immutable storage and can not be reallocated"
+        );
+    }
+
+    fn check_element<T: 'static>(&self) {
+        let expected = ElementType::of::<T>();
+        match self.element.get() {
+            Some(element) => assert!(
+                element.id == expected.id,
+                "buffer holds {}, not {}",
+                element.name,
+                expected.name
+            ),
+            None => self.element.set(Some(expected)),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len.get() == 0
+    }
+
+    /// Binds the buffer to `GL_COPY_WRITE_BUFFER` for the non-DSA functions,
+    /// a target that draw calls do not read.
+    fn edit_target(&self) -> gl::types::GLenum {
+        self.gl.bind_buffer(gl::COPY_WRITE_BUFFER, self.vbo);
+        gl::COPY_WRITE_BUFFER
+    }
+
+    /// Reallocates the buffer with `data`, for data that is uploaded once.
+    pub fn static_draw<T: 'static>(&self, data: &[T]) {
+        self.buffer_data(data, gl::STATIC_DRAW);
+    }
+
+    /// Reallocates the buffer with `data`, for data that is updated
+    /// repeatedly with `sub_data` or `update`.
+    pub fn dynamic_draw<T: 'static>(&self, data: &[T]) {
+        self.buffer_data(data, gl::DYNAMIC_DRAW);
+    }
+
+    /// Reallocates the buffer with `data`, for data that is replaced every
+    /// frame.
+    pub fn stream_draw<T: 'static>(&self, data: &[T]) {
+        self.buffer_data(data, gl::STREAM_DRAW);
+    }
+
+    fn buffer_data<T: 'static>(&self, data: &[T], usage: gl::types::GLenum) {
+        self.check_mutable();
+        self.set_element::<T>();
+        let size = std::mem::size_of_val(data) as gl::types::GLsizeiptr;
+        let ptr = data.as_ptr() as *const gl::types::GLvoid;
         let gl = &self.gl;
         unsafe {
-            gl.BufferData(
-                // target
-                B::BUFFER_TYPE,
-                // size of data in bytes
-                (data.len() * std::mem::size_of::<T>()) as gl::types::GLsizeiptr,
-                // pointer to data
-                data.as_ptr() as *const gl::types::GLvoid,
-                // usage
-                gl::STATIC_DRAW,
-            );
+            if self.dsa {
+                gl.NamedBufferData(self.vbo, size, ptr, usage);
+            } else {
+                gl.BufferData(self.edit_target(), size, ptr, usage);
+            }
+        }
+        let size = size as usize;
+        self.len.set(size);
+        self.capacity.set(size);
+    }
+
+    /// Makes room for at least `capacity` elements of `T` with
+    /// `GL_DYNAMIC_DRAW` usage. Growing the buffer discards its content.
+    pub fn reserve<T: 'static>(&self, capacity: usize) {
+        let size = capacity * std::mem::size_of::<T>();
+        let same_type = self
+            .element
+            .get()
+            .is_some_and(|element| element.id == TypeId::of::<T>());
+        if same_type && size <= self.capacity.get() {
+            return;
+        }
+        self.check_mutable();
+        self.set_element::<T>();
+        let gl_size = size as gl::types::GLsizeiptr;
+        unsafe {
+            if self.dsa {
+                self.gl
+                    .NamedBufferData(self.vbo, gl_size, std::ptr::null(), gl::DYNAMIC_DRAW);
+            } else {
+                self.gl.BufferData(
+                    self.edit_target(),
+                    gl_size,
+                    std::ptr::null(),
+                    gl::DYNAMIC_DRAW,
+                );
+            }
+        }
+        self.len.set(0);
+        self.capacity.set(size);
+    }
+
+    /// Overwrites the elements starting at element `offset` without
+    /// reallocating. Panics if the data does not fit into the capacity.
+    pub fn sub_data<T: 'static>(&self, offset: usize, data: &[T]) {
+        self.check_element::<T>();
+        let start = offset * std::mem::size_of::<T>();
+        let size = std::mem::size_of_val(data);
+        assert!(
+            start + size <= self.capacity.get(),
+            "sub_data of {} bytes at {} exceeds buffer capacity of {} bytes",
+            size,
+            start,
+            self.capacity.get()
+        );
+        let gl_start = start as gl::types::GLintptr;
+        let gl_size = size as gl::types::GLsizeiptr;
+        let ptr = data.as_ptr() as *const gl::types::GLvoid;
+        unsafe {
+            if self.dsa {
+                self.gl.NamedBufferSubData(self.vbo, gl_start, gl_size, ptr);
+            } else {
+                self.gl
+                    .BufferSubData(self.edit_target(), gl_start, gl_size, ptr);
+            }
+        }
+        self.len.set(self.len.get().max(start + size));
+    }
+
+    /// Allocates immutable storage (`glBufferStorage`, GL 4.4) for
+    /// `capacity` elements of `T`. `flags` are the `gl::MAP_*_BIT` and
+    /// `gl::DYNAMIC_STORAGE_BIT` flags; the buffer can not be reallocated
+    /// afterwards.
+    pub fn storage<T: 'static>(&self, capacity: usize, flags: gl::types::GLbitfield) {
+        self.check_mutable();
+        self.set_element::<T>();
+        let size = capacity * std::mem::size_of::<T>();
+        let gl_size = size as gl::types::GLsizeiptr;
+        unsafe {
+            if self.dsa {
+                self.gl
+                    .NamedBufferStorage(self.vbo, gl_size, std::ptr::null(), flags);
+            } else {
+                self.gl
+                    .BufferStorage(self.edit_target(), gl_size, std::ptr::null(), flags);
+            }
+        }
+        self.immutable.set(true);
+        self.len.set(size);
+        self.capacity.set(size);
+    }
+
+    /// Maps the whole buffer with `access` flags, returns null on failure.
+    pub fn map<T: 'static>(&self, access: gl::types::GLbitfield) -> *mut T {
+        self.check_element::<T>();
+        let size = self.capacity.get() as gl::types::GLsizeiptr;
+        unsafe {
+            if self.dsa {
+                self.gl.MapNamedBufferRange(self.vbo, 0, size, access) as *mut T
+            } else {
+                self.gl.MapBufferRange(self.edit_target(), 0, size, access) as *mut T
+            }
+        }
+    }
+
+    pub fn unmap(&self) {
+        unsafe {
+            if self.dsa {
+                self.gl.UnmapNamedBuffer(self.vbo);
+            } else {
+                self.gl.UnmapBuffer(self.edit_target());
+            }
         }
     }
+
+    /// Replaces the content with `data`, reusing the allocation if it is
+    /// large enough and reallocating with `dynamic_draw` otherwise.
+    pub fn update<T: 'static>(&self, data: &[T]) {
+        self.check_element::<T>();
+        if std::mem::size_of_val(data) > self.capacity.get() {
+            self.dynamic_draw(data);
+        } else {
+            self.sub_data(0, data);
+            self.len.set(std::mem::size_of_val(data));
+        }
+    }
+}
+
+impl<B> Buffer<B>
+where
+    B: IndexedBufferType,
+{
+    /// Binds the whole buffer to binding point `index`, see
+    /// `Program::bind_uniform_block` and `Program::bind_storage_block`.
+    pub fn bind_base(&self, index: u32) {
+        self.gl.bind_buffer_base(B::BUFFER_TYPE, index, self.vbo);
+    }
+
+    /// Binds `size` bytes starting at `offset` to binding point `index`.
+    /// `offset` has to be a multiple of `GL_UNIFORM_BUFFER_OFFSET_ALIGNMENT`
+    /// or `GL_SHADER_STORAGE_BUFFER_OFFSET_ALIGNMENT`.
+    pub fn bind_range(&self, index: u32, offset: usize, size: usize) {
+        self.gl.bind_buffer_range(
+            B::BUFFER_TYPE,
+            index,
+            self.vbo,
+            offset as gl::types::GLintptr,
+            size as gl::types::GLsizeiptr,
+        );
+    }
 }
 
 impl<B> Drop for Buffer<B>
@@ -78,48 +361,252 @@ where
     B: BufferType,
 {
     fn drop(&mut self) {
+        self.gl.delete_buffer(self.vbo);
+    }
+}
+
+// -=-=-=-=-=-=-=-=-=-=-=-=-=-=-=-=-=-=-=-=-=-=-=-=-=-=-=-
+
+/// Element type of an `IndexBuffer`.
+pub trait IndexType: Copy + 'static {
+    const INDEX_TYPE: gl::types::GLenum;
+}
+
+impl IndexType for u8 {
+    const INDEX_TYPE: gl::types::GLenum = gl::UNSIGNED_BYTE;
+}
+
+impl IndexType for u16 {
+    const INDEX_TYPE: gl::types::GLenum = gl::UNSIGNED_SHORT;
+}
+
+impl IndexType for u32 {
+    const INDEX_TYPE: gl::types::GLenum = gl::UNSIGNED_INT;
+}
+
+/// Element array buffer that only accepts indices of type `I`, so draw calls
+/// get the index type and count from the buffer.
+pub struct IndexBuffer<I>
+where
+    I: IndexType,
+{
+    buffer: ElementArrayBuffer,
+    _marker: ::std::marker::PhantomData<I>,
+}
+
+impl<I> IndexBuffer<I>
+where
+    I: IndexType,
+{
+    pub fn new(gl: &gl::Gl) -> Self {
+        IndexBuffer {
+            buffer: ElementArrayBuffer::new(gl),
+            _marker: ::std::marker::PhantomData,
+        }
+    }
+
+    pub fn bind(&self) {
+        self.buffer.bind();
+    }
+
+    pub fn unbind(&self) {
+        self.buffer.unbind();
+    }
+
+    /// Number of indices.
+    pub fn len(&self) -> usize {
+        self.buffer.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.buffer.is_empty()
+    }
+
+    pub fn index_type(&self) -> gl::types::GLenum {
+        I::INDEX_TYPE
+    }
+
+    pub fn static_draw(&self, indices: &[I]) {
+        self.buffer.static_draw(indices);
+    }
+
+    pub fn dynamic_draw(&self, indices: &[I]) {
+        self.buffer.dynamic_draw(indices);
+    }
+
+    pub fn stream_draw(&self, indices: &[I]) {
+        self.buffer.stream_draw(indices);
+    }
+
+    pub fn sub_data(&self, offset: usize, indices: &[I]) {
+        self.buffer.sub_data(offset, indices);
+    }
+
+    pub fn update(&self, indices: &[I]) {
+        self.buffer.update(indices);
+    }
+
+    /// Draws all indices. The vertex array that this buffer is bound in has
+    /// to be bound.
+    pub fn draw(&self, mode: gl::types::GLenum) {
+        self.draw_range(mode, 0, self.len());
+    }
+
+    /// Draws all indices `instances` times, see `glDrawElementsInstanced`.
+    pub fn draw_instanced(&self, mode: gl::types::GLenum, instances: usize) {
         unsafe {
-            self.gl.DeleteBuffers(1, &mut self.vbo);
-        };
+            self.buffer.gl.DrawElementsInstanced(
+                mode,
+                self.len() as gl::types::GLsizei,
+                I::INDEX_TYPE,
+                std::ptr::null(),
+                instances as gl::types::GLsizei,
+            );
+        }
+    }
+
+    /// Draws `count` indices starting at index `first`.
+    pub fn draw_range(&self, mode: gl::types::GLenum, first: usize, count: usize) {
+        assert!(
+            first + count <= self.len(),
+            "drawing indices {}..{} of {}",
+            first,
+            first + count,
+            self.len()
+        );
+        unsafe {
+            self.buffer.gl.DrawElements(
+                mode,
+                count as gl::types::GLsizei,
+                I::INDEX_TYPE,
+                (first * std::mem::size_of::<I>()) as *const gl::types::GLvoid,
+            );
+        }
     }
 }
 
 // -=-=-=-=-=-=-=-=-=-=-=-=-=-=-=-=-=-=-=-=-=-=-=-=-=-=-=-
 
+/// Vertex array object. The `set_*_buffer` functions use direct state
+/// access when available, like `Buffer`, and otherwise bind the vertex array
+/// and unbind it again.
 pub struct VertexArray {
     vao: gl::types::GLuint,
     gl: gl::Gl,
+    dsa: bool,
+    /// (buffer, divisor) of the buffer binding indices used with DSA
+    bindings: RefCell<Vec<(gl::types::GLuint, u32)>>,
 }
 
 impl VertexArray {
     pub fn new(gl: &gl::Gl) -> Self {
+        let dsa = gl.has_direct_state_access();
         let mut vao: gl::types::GLuint = 0;
         unsafe {
-            gl.GenVertexArrays(1, &mut vao);
+            if dsa {
+                gl.CreateVertexArrays(1, &mut vao);
+            } else {
+                gl.GenVertexArrays(1, &mut vao);
+            }
         };
         VertexArray {
             vao,
             gl: gl.clone(),
+            dsa,
+            bindings: RefCell::new(Vec::new()),
         }
     }
 
-    pub fn bind(&self) {
+    /// Sets up the attributes of `V` to read from `buffer`, which holds `V`s
+    /// from its start.
+    pub fn set_vertex_buffer<V: Vertex>(&self, buffer: &ArrayBuffer) {
+        if !self.dsa {
+            self.bind();
+            buffer.bind();
+            V::vertex_attrib_pointers(&self.gl);
+            self.unbind();
+            buffer.unbind();
+            return;
+        }
+
+        // with DSA the divisor belongs to the buffer binding, so the buffer
+        // gets one binding per divisor used in `V`
+        let stride = std::mem::size_of::<V>() as gl::types::GLsizei;
+        let mut set_up: Vec<u32> = Vec::new();
+        for attrib in V::vertex_layout() {
+            let binding = self.binding_index(buffer.vbo, attrib.divisor);
+            if !set_up.contains(&attrib.divisor) {
+                unsafe {
+                    self.gl
+                        .VertexArrayVertexBuffer(self.vao, binding, buffer.vbo, 0, stride);
+                    self.gl
+                        .VertexArrayBindingDivisor(self.vao, binding, attrib.divisor);
+                }
+                set_up.push(attrib.divisor);
+            }
+            let location = attrib.location as gl::types::GLuint;
+            unsafe {
+                attrib.format.vertex_array_attrib_format(
+                    &self.gl,
+                    self.vao,
+                    attrib.location,
+                    attrib.offset,
+                );
+                self.gl
+                    .VertexArrayAttribBinding(self.vao, location, binding);
+                self.gl.EnableVertexArrayAttrib(self.vao, location);
+            }
+        }
+    }
+
+    /// Buffer binding index for `buffer` with `divisor`. Setting up the same
+    /// buffer again reuses its bindings, new ones are limited by
+    /// `GL_MAX_VERTEX_ATTRIB_BINDINGS`.
+    fn binding_index(&self, buffer: gl::types::GLuint, divisor: u32) -> gl::types::GLuint {
+        let mut bindings = self.bindings.borrow_mut();
+        if let Some(index) = bindings.iter().position(|&b| b == (buffer, divisor)) {
+            return index as gl::types::GLuint;
+        }
+        let mut max: gl::types::GLint = 0;
         unsafe {
-            self.gl.BindVertexArray(self.vao);
-        };
+            self.gl
+                .GetIntegerv(gl::MAX_VERTEX_ATTRIB_BINDINGS, &mut max);
+        }
+        assert!(
+            bindings.len() < max as usize,
+            "vertex array uses more than {} buffer bindings",
+            max
+        );
+        bindings.push((buffer, divisor));
+        (bindings.len() - 1) as gl::types::GLuint
+    }
+
+    /// Makes `buffer` the element array buffer of this vertex array.
+    pub fn set_index_buffer<I: IndexType>(&self, buffer: &IndexBuffer<I>) {
+        if self.dsa {
+            unsafe {
+                self.gl
+                    .VertexArrayElementBuffer(self.vao, buffer.buffer.vbo);
+            }
+        } else {
+            self.bind();
+            buffer.bind();
+            self.unbind();
+            buffer.unbind();
+        }
+    }
+
+    pub fn bind(&self) {
+        self.gl.bind_vertex_array(self.vao);
     }
 
     pub fn unbind(&self) {
-        unsafe {
-            self.gl.BindVertexArray(0);
-        };
+        self.gl.bind_vertex_array(0);
     }
 }
 
 impl Drop for VertexArray {
     fn drop(&mut self) {
-        unsafe {
-            self.gl.DeleteVertexArrays(1, &mut self.vao);
-        };
+        self.gl.delete_vertex_array(self.vao);
     }
 }
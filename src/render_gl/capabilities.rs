@@ -0,0 +1,12 @@
+use gl;
+
+/// (major, minor) version of the current context, see `gl::Gl::version`.
+pub fn gl_version(gl: &gl::Gl) -> (i32, i32) {
+    gl.version()
+}
+
+/// Whether the context advertises the extension `name`, e.g.
+/// `"GL_EXT_texture_filter_anisotropic"`, see `gl::Gl::has_extension`.
+pub fn has_extension(gl: &gl::Gl, name: &str) -> bool {
+    gl.has_extension(name)
+}
@@ -1,141 +1,423 @@
+//! Vertex attribute types for `#[derive(VertexAttribPointers)]` fields.
+//!
+//! Names list the components, e.g. `f32_f32` is a `vec2` of floats and
+//! `f32_` a single float. Integer types come in three forms:
+//!
+//! * `i8_i8` is read as an integer (`ivec2`) through `glVertexAttribIPointer`
+//! * `i8_i8_float` is normalized to [-1, 1] (or [0, 1] when unsigned)
+//! * `i8_i8_scaled` is converted to float without normalizing, 7 -> 7.0
+//!
+//! `f64` types feed `double` inputs through `glVertexAttribLPointer`.
+
+use super::vertex::{AttribBaseType, AttribFormat, VertexAttribute};
 use gl;
+use half::f16;
+use nalgebra as na;
 
-#[allow(non_camel_case_types)]
-#[derive(Debug, Copy, Clone)]
-#[repr(C, packed)]
-pub struct f32_f32_f32 {
-    pub d0: f32,
-    pub d1: f32,
-    pub d2: f32,
-}
+/// Declares a vertex attribute type: the struct with its fields, `new`, the
+/// `VertexAttribute` impl and, for `d0`.. fields, `From` tuples, nalgebra
+/// vectors and (with one component) the scalar.
+///
+/// ```ignore
+/// vertex_format!(name: rust_type [fields], gl_type, components, normalized, base_type);
+/// ```
+///
+/// `base_type` is the `AttribBaseType` variant, i.e. `Int` or `UnsignedInt`
+/// for integer inputs. Packed formats have a single `inner` field holding
+/// all components and implement their `From` conversions by hand.
+macro_rules! vertex_format {
+    ($(#[$meta:meta])* $name:ident: $t:ty [$($field:ident),+], $gl_type:expr,
+     $components:expr, $normalized:expr, $base_type:ident) => {
+        $(#[$meta])*
+        #[allow(non_camel_case_types)]
+        #[derive(Debug, Copy, Clone)]
+        #[repr(C, packed)]
+        pub struct $name {
+            $(pub $field: $t,)+
+        }
 
-impl f32_f32_f32 {
-    pub fn new(d0: f32, d1: f32, d2: f32) -> Self {
-        f32_f32_f32 { d0, d1, d2 }
-    }
+        impl $name {
+            pub fn new($($field: $t),+) -> Self {
+                $name { $($field),+ }
+            }
+        }
 
-    pub unsafe fn vertex_attrib_pointer(
-        gl: &gl::Gl,
-        location: usize,
-        stride: usize,
-        offset: usize,
-    ) {
-        gl.EnableVertexAttribArray(location as gl::types::GLuint);
-        gl.VertexAttribPointer(
-            location as gl::types::GLuint,
-            3,
-            gl::FLOAT,
-            gl::FALSE,
-            stride as gl::types::GLint,
-            offset as *const gl::types::GLvoid,
-        );
-    }
+        impl VertexAttribute for $name {
+            const FORMAT: AttribFormat = AttribFormat {
+                components: $components,
+                gl_type: $gl_type,
+                normalized: $normalized,
+                base_type: AttribBaseType::$base_type,
+            };
+        }
+
+        vertex_format_from!($name, $t, $($field),+);
+    };
 }
 
-impl From<(f32, f32, f32)> for f32_f32_f32 {
-    fn from(other: (f32, f32, f32)) -> Self {
-        f32_f32_f32::new(other.0, other.1, other.2)
-    }
+/// `From` conversions of `vertex_format!` types, none for packed formats.
+macro_rules! vertex_format_from {
+    ($name:ident, $t:ty, d0) => {
+        impl From<$t> for $name {
+            fn from(d0: $t) -> Self {
+                $name::new(d0)
+            }
+        }
+
+        impl From<($t,)> for $name {
+            fn from(other: ($t,)) -> Self {
+                $name::new(other.0)
+            }
+        }
+    };
+    ($name:ident, $t:ty, d0, d1) => {
+        impl From<($t, $t)> for $name {
+            fn from(other: ($t, $t)) -> Self {
+                $name::new(other.0, other.1)
+            }
+        }
+
+        impl From<na::Vector2<$t>> for $name {
+            fn from(other: na::Vector2<$t>) -> Self {
+                $name::new(other.x, other.y)
+            }
+        }
+    };
+    ($name:ident, $t:ty, d0, d1, d2) => {
+        impl From<($t, $t, $t)> for $name {
+            fn from(other: ($t, $t, $t)) -> Self {
+                $name::new(other.0, other.1, other.2)
+            }
+        }
+
+        impl From<na::Vector3<$t>> for $name {
+            fn from(other: na::Vector3<$t>) -> Self {
+                $name::new(other.x, other.y, other.z)
+            }
+        }
+    };
+    ($name:ident, $t:ty, d0, d1, d2, d3) => {
+        impl From<($t, $t, $t, $t)> for $name {
+            fn from(other: ($t, $t, $t, $t)) -> Self {
+                $name::new(other.0, other.1, other.2, other.3)
+            }
+        }
+
+        impl From<na::Vector4<$t>> for $name {
+            fn from(other: na::Vector4<$t>) -> Self {
+                $name::new(other.x, other.y, other.z, other.w)
+            }
+        }
+    };
+    ($name:ident, $t:ty, inner) => {};
 }
 
-// -=-=-=-=-=-=-=-=-=-=-=-=-=-=-=-=-=-=-=-=-=-=-=-=-=-=-=-
+vertex_format!(f32_: f32 [d0], gl::FLOAT, 1, false, Float);
+vertex_format!(f32_f32: f32 [d0, d1], gl::FLOAT, 2, false, Float);
+vertex_format!(f32_f32_f32: f32 [d0, d1, d2], gl::FLOAT, 3, false, Float);
+vertex_format!(f32_f32_f32_f32: f32 [d0, d1, d2, d3], gl::FLOAT, 4, false, Float);
+
+vertex_format!(f16_: f16 [d0], gl::HALF_FLOAT, 1, false, Float);
+vertex_format!(f16_f16: f16 [d0, d1], gl::HALF_FLOAT, 2, false, Float);
+vertex_format!(f16_f16_f16: f16 [d0, d1, d2], gl::HALF_FLOAT, 3, false, Float);
+vertex_format!(f16_f16_f16_f16: f16 [d0, d1, d2, d3], gl::HALF_FLOAT, 4, false, Float);
+
+vertex_format!(f64_: f64 [d0], gl::DOUBLE, 1, false, Double);
+vertex_format!(f64_f64: f64 [d0, d1], gl::DOUBLE, 2, false, Double);
+vertex_format!(f64_f64_f64: f64 [d0, d1, d2], gl::DOUBLE, 3, false, Double);
+vertex_format!(f64_f64_f64_f64: f64 [d0, d1, d2, d3], gl::DOUBLE, 4, false, Double);
+
+vertex_format!(i8_: i8 [d0], gl::BYTE, 1, false, Int);
+vertex_format!(i8_i8: i8 [d0, d1], gl::BYTE, 2, false, Int);
+vertex_format!(i8_i8_i8: i8 [d0, d1, d2], gl::BYTE, 3, false, Int);
+vertex_format!(i8_i8_i8_i8: i8 [d0, d1, d2, d3], gl::BYTE, 4, false, Int);
+
+vertex_format!(u8_: u8 [d0], gl::UNSIGNED_BYTE, 1, false, UnsignedInt);
+vertex_format!(u8_u8: u8 [d0, d1], gl::UNSIGNED_BYTE, 2, false, UnsignedInt);
+vertex_format!(u8_u8_u8: u8 [d0, d1, d2], gl::UNSIGNED_BYTE, 3, false, UnsignedInt);
+vertex_format!(u8_u8_u8_u8: u8 [d0, d1, d2, d3], gl::UNSIGNED_BYTE, 4, false, UnsignedInt);
+
+vertex_format!(i16_: i16 [d0], gl::SHORT, 1, false, Int);
+vertex_format!(i16_i16: i16 [d0, d1], gl::SHORT, 2, false, Int);
+vertex_format!(i16_i16_i16: i16 [d0, d1, d2], gl::SHORT, 3, false, Int);
+vertex_format!(i16_i16_i16_i16: i16 [d0, d1, d2, d3], gl::SHORT, 4, false, Int);
+
+vertex_format!(u16_: u16 [d0], gl::UNSIGNED_SHORT, 1, false, UnsignedInt);
+vertex_format!(u16_u16: u16 [d0, d1], gl::UNSIGNED_SHORT, 2, false, UnsignedInt);
+vertex_format!(u16_u16_u16: u16 [d0, d1, d2], gl::UNSIGNED_SHORT, 3, false, UnsignedInt);
+vertex_format!(u16_u16_u16_u16: u16 [d0, d1, d2, d3], gl::UNSIGNED_SHORT, 4, false, UnsignedInt);
+
+vertex_format!(i32_: i32 [d0], gl::INT, 1, false, Int);
+vertex_format!(i32_i32: i32 [d0, d1], gl::INT, 2, false, Int);
+vertex_format!(i32_i32_i32: i32 [d0, d1, d2], gl::INT, 3, false, Int);
+vertex_format!(i32_i32_i32_i32: i32 [d0, d1, d2, d3], gl::INT, 4, false, Int);
+
+vertex_format!(u32_: u32 [d0], gl::UNSIGNED_INT, 1, false, UnsignedInt);
+vertex_format!(u32_u32: u32 [d0, d1], gl::UNSIGNED_INT, 2, false, UnsignedInt);
+vertex_format!(u32_u32_u32: u32 [d0, d1, d2], gl::UNSIGNED_INT, 3, false, UnsignedInt);
+vertex_format!(u32_u32_u32_u32: u32 [d0, d1, d2, d3], gl::UNSIGNED_INT, 4, false, UnsignedInt);
+
+vertex_format!(i8_float: i8 [d0], gl::BYTE, 1, true, Float);
+vertex_format!(i8_i8_float: i8 [d0, d1], gl::BYTE, 2, true, Float);
+vertex_format!(i8_i8_i8_float: i8 [d0, d1, d2], gl::BYTE, 3, true, Float);
+vertex_format!(i8_i8_i8_i8_float: i8 [d0, d1, d2, d3], gl::BYTE, 4, true, Float);
+
+vertex_format!(u8_float: u8 [d0], gl::UNSIGNED_BYTE, 1, true, Float);
+vertex_format!(u8_u8_float: u8 [d0, d1], gl::UNSIGNED_BYTE, 2, true, Float);
+vertex_format!(u8_u8_u8_float: u8 [d0, d1, d2], gl::UNSIGNED_BYTE, 3, true, Float);
+vertex_format!(u8_u8_u8_u8_float: u8 [d0, d1, d2, d3], gl::UNSIGNED_BYTE, 4, true, Float);
+
+vertex_format!(i16_float: i16 [d0], gl::SHORT, 1, true, Float);
+vertex_format!(i16_i16_float: i16 [d0, d1], gl::SHORT, 2, true, Float);
+vertex_format!(i16_i16_i16_float: i16 [d0, d1, d2], gl::SHORT, 3, true, Float);
+vertex_format!(i16_i16_i16_i16_float: i16 [d0, d1, d2, d3], gl::SHORT, 4, true, Float);
+
+vertex_format!(u16_float: u16 [d0], gl::UNSIGNED_SHORT, 1, true, Float);
+vertex_format!(u16_u16_float: u16 [d0, d1], gl::UNSIGNED_SHORT, 2, true, Float);
+vertex_format!(u16_u16_u16_float: u16 [d0, d1, d2], gl::UNSIGNED_SHORT, 3, true, Float);
+vertex_format!(u16_u16_u16_u16_float: u16 [d0, d1, d2, d3], gl::UNSIGNED_SHORT, 4, true, Float);
+
+vertex_format!(i32_float: i32 [d0], gl::INT, 1, true, Float);
+vertex_format!(i32_i32_float: i32 [d0, d1], gl::INT, 2, true, Float);
+vertex_format!(i32_i32_i32_float: i32 [d0, d1, d2], gl::INT, 3, true, Float);
+vertex_format!(i32_i32_i32_i32_float: i32 [d0, d1, d2, d3], gl::INT, 4, true, Float);
+
+vertex_format!(u32_float: u32 [d0], gl::UNSIGNED_INT, 1, true, Float);
+vertex_format!(u32_u32_float: u32 [d0, d1], gl::UNSIGNED_INT, 2, true, Float);
+vertex_format!(u32_u32_u32_float: u32 [d0, d1, d2], gl::UNSIGNED_INT, 3, true, Float);
+vertex_format!(u32_u32_u32_u32_float: u32 [d0, d1, d2, d3], gl::UNSIGNED_INT, 4, true, Float);
+
+vertex_format!(i8_scaled: i8 [d0], gl::BYTE, 1, false, Float);
+vertex_format!(i8_i8_scaled: i8 [d0, d1], gl::BYTE, 2, false, Float);
+vertex_format!(i8_i8_i8_scaled: i8 [d0, d1, d2], gl::BYTE, 3, false, Float);
+vertex_format!(i8_i8_i8_i8_scaled: i8 [d0, d1, d2, d3], gl::BYTE, 4, false, Float);
+
+vertex_format!(u8_scaled: u8 [d0], gl::UNSIGNED_BYTE, 1, false, Float);
+vertex_format!(u8_u8_scaled: u8 [d0, d1], gl::UNSIGNED_BYTE, 2, false, Float);
+vertex_format!(u8_u8_u8_scaled: u8 [d0, d1, d2], gl::UNSIGNED_BYTE, 3, false, Float);
+vertex_format!(u8_u8_u8_u8_scaled: u8 [d0, d1, d2, d3], gl::UNSIGNED_BYTE, 4, false, Float);
+
+vertex_format!(i16_scaled: i16 [d0], gl::SHORT, 1, false, Float);
+vertex_format!(i16_i16_scaled: i16 [d0, d1], gl::SHORT, 2, false, Float);
+vertex_format!(i16_i16_i16_scaled: i16 [d0, d1, d2], gl::SHORT, 3, false, Float);
+vertex_format!(i16_i16_i16_i16_scaled: i16 [d0, d1, d2, d3], gl::SHORT, 4, false, Float);
+
+vertex_format!(u16_scaled: u16 [d0], gl::UNSIGNED_SHORT, 1, false, Float);
+vertex_format!(u16_u16_scaled: u16 [d0, d1], gl::UNSIGNED_SHORT, 2, false, Float);
+vertex_format!(u16_u16_u16_scaled: u16 [d0, d1, d2], gl::UNSIGNED_SHORT, 3, false, Float);
+vertex_format!(u16_u16_u16_u16_scaled: u16 [d0, d1, d2, d3], gl::UNSIGNED_SHORT, 4, false, Float);
+
+vertex_format!(i32_scaled: i32 [d0], gl::INT, 1, false, Float);
+vertex_format!(i32_i32_scaled: i32 [d0, d1], gl::INT, 2, false, Float);
+vertex_format!(i32_i32_i32_scaled: i32 [d0, d1, d2], gl::INT, 3, false, Float);
+vertex_format!(i32_i32_i32_i32_scaled: i32 [d0, d1, d2, d3], gl::INT, 4, false, Float);
+
+vertex_format!(u32_scaled: u32 [d0], gl::UNSIGNED_INT, 1, false, Float);
+vertex_format!(u32_u32_scaled: u32 [d0, d1], gl::UNSIGNED_INT, 2, false, Float);
+vertex_format!(u32_u32_u32_scaled: u32 [d0, d1, d2], gl::UNSIGNED_INT, 3, false, Float);
+vertex_format!(u32_u32_u32_u32_scaled: u32 [d0, d1, d2, d3], gl::UNSIGNED_INT, 4, false, Float);
+
+/// Old name of `i8_scaled`.
+#[allow(non_camel_case_types)]
+pub type gl_i8 = i8_scaled;
+
+/// Old name of `i8_float`.
 #[allow(non_camel_case_types)]
-#[derive(Debug, Copy, Clone)]
-#[repr(C, packed)]
-pub struct u2_u10_u10_u10_rev_float {
-    pub inner: vec_2_10_10_10::Vector,
+pub type gl_i8_float = i8_float;
+
+// -=-=-=-=-=-=-=-=-=-=-=-=-=-=-=-=-=-=-=-=-=-=-=-=-=-=-=-
+
+vertex_format!(
+    /// Normalized unsigned `vec4` packed into 32 bits, x in the low 10 bits.
+    /// Components are clamped to [0, 1].
+    u2_u10_u10_u10_rev_float: u32 [inner],
+    gl::UNSIGNED_INT_2_10_10_10_REV, 4, true, Float
+);
+
+/// Unorm of `bits` bits.
+fn pack_unorm(value: f32, bits: u32) -> u32 {
+    let max = ((1 << bits) - 1) as f32;
+    (value.clamp(0.0, 1.0) * max).round() as u32
 }
 
 impl From<(f32, f32, f32, f32)> for u2_u10_u10_u10_rev_float {
     fn from(other: (f32, f32, f32, f32)) -> Self {
         let (x, y, z, w) = other;
         u2_u10_u10_u10_rev_float {
-            inner: vec_2_10_10_10::Vector::new(x, y, z, w),
+            inner: pack_unorm(x, 10)
+                | pack_unorm(y, 10) << 10
+                | pack_unorm(z, 10) << 20
+                | pack_unorm(w, 2) << 30,
         }
     }
 }
 
-impl u2_u10_u10_u10_rev_float {
-    pub unsafe fn vertex_attrib_pointer(
-        gl: &gl::Gl,
-        location: usize,
-        stride: usize,
-        offset: usize,
-    ) {
-        gl.EnableVertexAttribArray(location as gl::types::GLuint);
-        gl.VertexAttribPointer(
-            location as gl::types::GLuint,
-            4,
-            gl::UNSIGNED_INT_2_10_10_10_REV,
-            gl::TRUE,
-            stride as gl::types::GLint,
-            offset as *const gl::types::GLvoid,
-        );
+impl From<na::Vector4<f32>> for u2_u10_u10_u10_rev_float {
+    fn from(other: na::Vector4<f32>) -> Self {
+        (other.x, other.y, other.z, other.w).into()
     }
 }
 
 // -=-=-=-=-=-=-=-=-=-=-=-=-=-=-=-=-=-=-=-=-=-=-=-=-=-=-=-
-#[allow(non_camel_case_types)]
-#[derive(Debug, Copy, Clone)]
-#[repr(C, packed)]
-pub struct gl_i8 {
-    pub d0: i8,
+
+vertex_format!(
+    /// Normalized signed `vec4` packed into 32 bits, x in the low 10 bits.
+    /// Components are clamped to [-1, 1]; w only has the values -1, 0 and 1.
+    i2_i10_i10_i10_rev_float: u32 [inner],
+    gl::INT_2_10_10_10_REV, 4, true, Float
+);
+
+/// Two's complement snorm of `bits` bits, as read by GL 4.2+.
+fn pack_snorm(value: f32, bits: u32) -> u32 {
+    let max = ((1 << (bits - 1)) - 1) as f32;
+    let packed = (value.clamp(-1.0, 1.0) * max).round() as i32;
+    packed as u32 & ((1 << bits) - 1)
 }
 
-impl From<i8> for gl_i8 {
-    fn from(d0: i8) -> Self {
-        gl_i8 { d0 }
+impl From<(f32, f32, f32, f32)> for i2_i10_i10_i10_rev_float {
+    fn from(other: (f32, f32, f32, f32)) -> Self {
+        let (x, y, z, w) = other;
+        i2_i10_i10_i10_rev_float {
+            inner: pack_snorm(x, 10)
+                | pack_snorm(y, 10) << 10
+                | pack_snorm(z, 10) << 20
+                | pack_snorm(w, 2) << 30,
+        }
     }
 }
 
-impl gl_i8 {
-    pub unsafe fn vertex_attrib_pointer(
-        gl: &gl::Gl,
-        location: usize,
-        stride: usize,
-        offset: usize,
-    ) {
-        gl.EnableVertexAttribArray(location as gl::types::GLuint);
-        gl.VertexAttribPointer(
-            location as gl::types::GLuint,
-            1,
-            gl::BYTE,
-            gl::FALSE,
-            stride as gl::types::GLint,
-            offset as *const gl::types::GLvoid,
-        );
+impl From<na::Vector4<f32>> for i2_i10_i10_i10_rev_float {
+    fn from(other: na::Vector4<f32>) -> Self {
+        (other.x, other.y, other.z, other.w).into()
     }
 }
 
 // -=-=-=-=-=-=-=-=-=-=-=-=-=-=-=-=-=-=-=-=-=-=-=-=-=-=-=-
-#[allow(non_camel_case_types)]
-#[derive(Debug, Copy, Clone)]
-#[repr(C, packed)]
-pub struct gl_i8_float {
-    pub d0: i8,
+
+vertex_format!(
+    /// Unsigned float `vec3` packed into 32 bits: 11 bit x and y, 10 bit z,
+    /// all with a 5 bit exponent and no sign. Needs GL 4.4 or
+    /// ARB_vertex_type_10f_11f_11f_rev. Negative values become 0, mantissas
+    /// are rounded toward zero, so finite values above the largest one
+    /// (65024 for x and y, 64512 for z) become it. Infinity and NaN stay.
+    f10_f11_f11_rev: u32 [inner],
+    gl::UNSIGNED_INT_10F_11F_11F_REV, 3, false, Float
+);
+
+/// Unsigned float with a 5 bit exponent (bias 15) and `mantissa_bits` bits
+/// of mantissa, cut from the bits of the f32.
+fn pack_unsigned_float(value: f32, mantissa_bits: u32) -> u32 {
+    let infinity = 0x1f << mantissa_bits;
+    if value.is_nan() {
+        return infinity | 1 << (mantissa_bits - 1);
+    }
+    if value <= 0.0 {
+        return 0;
+    }
+    if value == f32::INFINITY {
+        return infinity;
+    }
+    let bits = value.to_bits();
+    let exponent = (bits >> 23) as i32 - 127 + 15;
+    let mantissa = bits & 0x7f_ffff;
+    let shift = 23 - mantissa_bits;
+    if exponent >= 0x1f {
+        // largest finite value
+        infinity - 1
+    } else if exponent > 0 {
+        (exponent as u32) << mantissa_bits | mantissa >> shift
+    } else {
+        // denormal, the implicit leading one becomes part of the mantissa
+        let shift = shift + (1 - exponent) as u32;
+        if shift < 24 {
+            (mantissa | 0x80_0000) >> shift
+        } else {
+            0
+        }
+    }
+}
+
+impl From<(f32, f32, f32)> for f10_f11_f11_rev {
+    fn from(other: (f32, f32, f32)) -> Self {
+        let (x, y, z) = other;
+        f10_f11_f11_rev {
+            inner: pack_unsigned_float(x, 6)
+                | pack_unsigned_float(y, 6) << 11
+                | pack_unsigned_float(z, 5) << 22,
+        }
+    }
 }
 
-impl From<i8> for gl_i8_float {
-    fn from(d0: i8) -> Self {
-        gl_i8_float { d0 }
+impl From<na::Vector3<f32>> for f10_f11_f11_rev {
+    fn from(other: na::Vector3<f32>) -> Self {
+        (other.x, other.y, other.z).into()
     }
 }
 
-impl gl_i8_float {
-    pub unsafe fn vertex_attrib_pointer(
-        gl: &gl::Gl,
-        location: usize,
-        stride: usize,
-        offset: usize,
-    ) {
-        gl.EnableVertexAttribArray(location as gl::types::GLuint);
-        gl.VertexAttribPointer(
-            location as gl::types::GLuint,
-            1,
-            gl::BYTE,
-            gl::TRUE,
-            stride as gl::types::GLint,
-            offset as *const gl::types::GLvoid,
-        );
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn unorm_2_10_10_10() {
+        let packed = |x, y, z, w| u2_u10_u10_u10_rev_float::from((x, y, z, w)).inner;
+        assert_eq!(packed(1.0, 0.0, 0.0, 1.0), 0xC000_03FF);
+        assert_eq!(packed(0.0, 1.0, 0.0, 0.0), 0x000F_FC00);
+        assert_eq!(packed(0.0, 0.0, 1.0, 0.0), 0x3FF0_0000);
+        assert_eq!(packed(0.5, 0.0, 0.0, 1.0 / 3.0), 0x4000_0200);
+    }
+
+    #[test]
+    fn snorm_2_10_10_10() {
+        let packed = |x, y, z, w| i2_i10_i10_i10_rev_float::from((x, y, z, w)).inner;
+        assert_eq!(packed(1.0, -1.0, 0.0, -1.0), 0xC008_05FF);
+        assert_eq!(packed(-2.0, 0.5, 5.0, 1.0), 0x5FF4_0201);
+        assert_eq!(pack_snorm(1.0, 10), 0x1FF);
+        assert_eq!(pack_snorm(-1.0, 10), 0x201);
+        assert_eq!(pack_snorm(-1.0, 2), 0b11);
+        assert_eq!(pack_snorm(1.0, 2), 0b01);
+    }
+
+    #[test]
+    fn unsigned_float_10_11_11() {
+        // 1.0 has the biased exponent 15 and no mantissa
+        assert_eq!(pack_unsigned_float(1.0, 6), 15 << 6);
+        assert_eq!(pack_unsigned_float(1.0, 5), 15 << 5);
+        assert_eq!(pack_unsigned_float(1.5, 6), 15 << 6 | 0b10_0000);
+        // rounded toward zero
+        assert_eq!(pack_unsigned_float(1.0 + 0.99 / 64.0, 6), 15 << 6);
+        assert_eq!(pack_unsigned_float(1.0 + 1.0 / 64.0, 6), 15 << 6 | 1);
+        assert_eq!(pack_unsigned_float(1e6, 6), 0x7BF);
+        // the smallest denormal, 2^-14 / 64
+        assert_eq!(pack_unsigned_float(2f32.powi(-20), 6), 1);
+        assert_eq!(pack_unsigned_float(2f32.powi(-21), 6), 0);
+
+        let packed = f10_f11_f11_rev::from((1.0, 2.0, 0.5)).inner;
+        assert_eq!(packed, 15 << 6 | 16 << 17 | 14 << 27);
+    }
+
+    #[test]
+    fn unsigned_float_special_values() {
+        assert_eq!(pack_unsigned_float(-1.0, 6), 0);
+        assert_eq!(pack_unsigned_float(-0.0, 6), 0);
+        assert_eq!(pack_unsigned_float(f32::NEG_INFINITY, 5), 0);
+        assert_eq!(pack_unsigned_float(f32::INFINITY, 6), 0x7C0);
+        assert_eq!(pack_unsigned_float(f32::INFINITY, 5), 0x3E0);
+        for &mantissa_bits in &[5, 6] {
+            let nan = pack_unsigned_float(f32::NAN, mantissa_bits);
+            let infinity = 0x1f << mantissa_bits;
+            assert_eq!(nan & infinity, infinity);
+            assert_ne!(nan & !infinity, 0);
+            assert!(nan < 1 << (mantissa_bits + 5));
+        }
+    }
+
+    #[test]
+    fn unorm_is_clamped() {
+        assert_eq!(pack_unorm(-0.5, 10), 0);
+        assert_eq!(pack_unorm(2.0, 10), 0x3FF);
+        assert_eq!(pack_unorm(7.0, 2), 3);
+        let packed = u2_u10_u10_u10_rev_float::from((-1.0, 1.5, 0.0, 9.0));
+        assert_eq!({ packed.inner }, 0xC00F_FC00);
     }
 }
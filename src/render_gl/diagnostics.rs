@@ -0,0 +1,393 @@
+use super::preprocessor::SourceFile;
+use std::fmt;
+use std::path::PathBuf;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Severity {
+    Error,
+    Warning,
+}
+
+impl fmt::Display for Severity {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Severity::Error => write!(f, "error"),
+            Severity::Warning => write!(f, "warning"),
+        }
+    }
+}
+
+/// One message of a shader info log, mapped back to the file it refers to.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Diagnostic {
+    pub severity: Severity,
+    /// resource name of the file, `None` if the log line had no location
+    pub file: Option<String>,
+    pub path: Option<PathBuf>,
+    pub line: Option<usize>,
+    pub column: Option<usize>,
+    pub message: String,
+    /// the offending line of `file`
+    pub source_line: Option<String>,
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}: ", self.severity)?;
+        if let Some(ref file) = self.file {
+            write!(f, "{}:", file)?;
+            if let Some(line) = self.line {
+                write!(f, "{}:", line)?;
+            }
+            if let Some(column) = self.column {
+                write!(f, "{}:", column)?;
+            }
+            write!(f, " ")?;
+        }
+        writeln!(f, "{}", self.message)?;
+
+        if let (Some(line), Some(source_line)) = (self.line, &self.source_line) {
+            let gutter = line.to_string();
+            writeln!(f, "  {} | {}", gutter, source_line)?;
+            if let Some(column) = self.column {
+                let indent: String = source_line
+                    .chars()
+                    .take(column.saturating_sub(1))
+                    .map(|c| if c == '\t' { '\t' } else { ' ' })
+                    .collect();
+                writeln!(f, "  {} | {}^", " ".repeat(gutter.len()), indent)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Diagnostics(pub Vec<Diagnostic>);
+
+impl Diagnostics {
+    /// Parses a shader info log and resolves the source string numbers and
+    /// lines against the files of the preprocessed shader.
+    pub fn from_log(log: &str, files: &[SourceFile]) -> Self {
+        // the log may still contain the 0 terminator written by the driver
+        let log = log.split('\0').next().unwrap_or("");
+        Diagnostics(
+            log.lines()
+                .map(|line| line.trim())
+                .filter(|line| !line.is_empty())
+                .map(|line| resolve(parse_log_line(line), files))
+                .collect(),
+        )
+    }
+
+    pub fn errors(&self) -> impl Iterator<Item = &Diagnostic> {
+        self.0.iter().filter(|d| d.severity == Severity::Error)
+    }
+}
+
+impl fmt::Display for Diagnostics {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for d in &self.0 {
+            write!(f, "{}", d)?;
+        }
+        Ok(())
+    }
+}
+
+// -=-=-=-=-=-=-=-=-=-=-=-=-=-=-=-=-=-=-=-=-=-=-=-=-=-=-=-
+// log parsing
+
+#[derive(Debug, PartialEq)]
+struct LogLine<'a> {
+    severity: Severity,
+    source: Option<usize>,
+    line: Option<usize>,
+    column: Option<usize>,
+    message: &'a str,
+}
+
+fn resolve(log_line: LogLine, files: &[SourceFile]) -> Diagnostic {
+    let file = log_line.source.and_then(|i| files.get(i));
+    let source_line = match (file, log_line.line) {
+        (Some(file), Some(line)) if line > 0 => {
+            file.content.lines().nth(line - 1).map(String::from)
+        }
+        _ => None,
+    };
+    Diagnostic {
+        severity: log_line.severity,
+        file: file.map(|f| f.name.clone()),
+        path: file.map(|f| f.path.clone()),
+        line: log_line.line,
+        column: log_line.column,
+        message: log_line.message.to_string(),
+        source_line,
+    }
+}
+
+/// Understands the formats of the common drivers:
+///
+/// - Mesa: `0:12(5): error: message`, also with a word before the
+///   severity as in `0:12(5): preprocessor error: message`
+/// - NVIDIA: `0(12) : error C1008: message`
+/// - AMD / Apple / glslang: `ERROR: 0:12: message`
+///
+/// Anything else is kept as a message without location.
+fn parse_log_line(line: &str) -> LogLine<'_> {
+    parse_mesa(line)
+        .or_else(|| parse_nvidia(line))
+        .or_else(|| parse_amd(line))
+        .unwrap_or_else(|| {
+            let (severity, message) = match severity(line) {
+                Some((severity, rest)) => (severity, rest.trim_start_matches(':').trim()),
+                None => (Severity::Error, line),
+            };
+            LogLine {
+                severity,
+                source: None,
+                line: None,
+                column: None,
+                message,
+            }
+        })
+}
+
+fn parse_mesa(line: &str) -> Option<LogLine<'_>> {
+    let (source, rest) = number(line)?;
+    let (line_no, rest) = number(rest.strip_prefix(':')?)?;
+    let (column, rest) = number(rest.strip_prefix('(')?)?;
+    let rest = rest.strip_prefix("):")?.trim_start();
+    let (severity, rest) = severity(rest).or_else(|| {
+        let (word, rest) = rest.split_at(rest.find(' ')?);
+        if !word.chars().all(|c| c.is_ascii_alphabetic()) {
+            return None;
+        }
+        severity(rest.trim_start())
+    })?;
+    let message = rest.strip_prefix(':')?.trim();
+    Some(LogLine {
+        severity,
+        source: Some(source),
+        line: Some(line_no),
+        column: Some(column),
+        message,
+    })
+}
+
+fn parse_nvidia(line: &str) -> Option<LogLine<'_>> {
+    let (source, rest) = number(line)?;
+    let (line_no, rest) = number(rest.strip_prefix('(')?)?;
+    let rest = rest
+        .strip_prefix(')')?
+        .trim_start()
+        .strip_prefix(':')?
+        .trim_start();
+    let (severity, rest) = severity(rest)?;
+    // skip the error code, e.g. "C1008"
+    let message = match rest.find(':') {
+        Some(i) => rest[i + 1..].trim(),
+        None => rest.trim(),
+    };
+    Some(LogLine {
+        severity,
+        source: Some(source),
+        line: Some(line_no),
+        column: None,
+        message,
+    })
+}
+
+fn parse_amd(line: &str) -> Option<LogLine<'_>> {
+    let (severity, rest) = severity(line)?;
+    let rest = rest.strip_prefix(':')?.trim_start();
+    let (source, rest) = number(rest)?;
+    let (line_no, rest) = number(rest.strip_prefix(':')?)?;
+    let message = rest.strip_prefix(':')?.trim();
+    Some(LogLine {
+        severity,
+        source: Some(source),
+        line: Some(line_no),
+        column: None,
+        message,
+    })
+}
+
+fn number(s: &str) -> Option<(usize, &str)> {
+    let end = s
+        .char_indices()
+        .find(|(_, c)| !c.is_ascii_digit())
+        .map(|(i, _)| i)
+        .unwrap_or_else(|| s.len());
+    if end == 0 {
+        return None;
+    }
+    s[..end].parse().ok().map(|n| (n, &s[end..]))
+}
+
+fn severity(s: &str) -> Option<(Severity, &str)> {
+    const SEVERITIES: [(&str, Severity); 4] = [
+        ("error", Severity::Error),
+        ("ERROR", Severity::Error),
+        ("warning", Severity::Warning),
+        ("WARNING", Severity::Warning),
+    ];
+    SEVERITIES
+        .iter()
+        .find(|(prefix, _)| s.starts_with(prefix))
+        .map(|&(prefix, severity)| (severity, s[prefix.len()..].trim_start()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn files() -> Vec<SourceFile> {
+        vec![
+            SourceFile {
+                name: "shaders/triangle.frag".into(),
+                path: PathBuf::from("/assets/shaders/triangle.frag"),
+                content: "#version 330 core\nout vec4 Color;\nvoid main() {\n\tColor = foo;\n}\n"
+                    .into(),
+            },
+            SourceFile {
+                name: "shaders/common.glsl".into(),
+                path: PathBuf::from("/assets/shaders/common.glsl"),
+                content: "float f() {\n    return 1;\n}\n".into(),
+            },
+        ]
+    }
+
+    #[test]
+    fn mesa() {
+        assert_eq!(
+            parse_log_line("0:4(10): error: `foo' undeclared"),
+            LogLine {
+                severity: Severity::Error,
+                source: Some(0),
+                line: Some(4),
+                column: Some(10),
+                message: "`foo' undeclared",
+            }
+        );
+    }
+
+    #[test]
+    fn nvidia() {
+        assert_eq!(
+            parse_log_line("0(4) : error C1008: undefined variable \"foo\""),
+            LogLine {
+                severity: Severity::Error,
+                source: Some(0),
+                line: Some(4),
+                column: None,
+                message: "undefined variable \"foo\"",
+            }
+        );
+    }
+
+    #[test]
+    fn amd() {
+        assert_eq!(
+            parse_log_line("ERROR: 1:2: 'return' : cannot convert return value"),
+            LogLine {
+                severity: Severity::Error,
+                source: Some(1),
+                line: Some(2),
+                column: None,
+                message: "'return' : cannot convert return value",
+            }
+        );
+    }
+
+    #[test]
+    fn mesa_word_before_severity() {
+        assert_eq!(
+            parse_log_line("0:1(1): preprocessor error: Invalid tokens after #"),
+            LogLine {
+                severity: Severity::Error,
+                source: Some(0),
+                line: Some(1),
+                column: Some(1),
+                message: "Invalid tokens after #",
+            }
+        );
+        assert_eq!(
+            parse_log_line("1:7(3): preprocessor warning: extra tokens").severity,
+            Severity::Warning
+        );
+    }
+
+    #[test]
+    fn unparsed_line_is_kept_as_error() {
+        let line = "0:1(1): unexpected end of input";
+        assert_eq!(
+            parse_log_line(line),
+            LogLine {
+                severity: Severity::Error,
+                source: None,
+                line: None,
+                column: None,
+                message: line,
+            }
+        );
+        assert_eq!(
+            parse_log_line("WARNING: Output of vertex shader not read").message,
+            "Output of vertex shader not read"
+        );
+    }
+
+    #[test]
+    fn multiple_messages_are_resolved_against_files() {
+        let log = "0:4(10): error: `foo' undeclared\n\
+                   1:2(12): warning: implicit conversion from int to float\n\
+                   \n\
+                   0:4(2): error: type mismatch\n\0garbage";
+        let diagnostics = Diagnostics::from_log(log, &files());
+        assert_eq!(diagnostics.0.len(), 3);
+        assert_eq!(diagnostics.errors().count(), 2);
+
+        let warning = &diagnostics.0[1];
+        assert_eq!(warning.severity, Severity::Warning);
+        assert_eq!(warning.file.as_deref(), Some("shaders/common.glsl"));
+        assert_eq!(
+            warning.path,
+            Some(PathBuf::from("/assets/shaders/common.glsl"))
+        );
+        assert_eq!(warning.source_line.as_deref(), Some("    return 1;"));
+
+        let error = &diagnostics.0[2];
+        assert_eq!(error.file.as_deref(), Some("shaders/triangle.frag"));
+        assert_eq!(error.line, Some(4));
+        assert_eq!(error.message, "type mismatch");
+    }
+
+    #[test]
+    fn unknown_source_number_has_no_file() {
+        let diagnostics = Diagnostics::from_log("7:1(1): error: nope", &files());
+        assert_eq!(diagnostics.0[0].file, None);
+        assert_eq!(diagnostics.0[0].source_line, None);
+        assert_eq!(diagnostics.0[0].to_string(), "error: nope\n");
+    }
+
+    #[test]
+    fn display_points_at_column() {
+        let diagnostics = Diagnostics::from_log("1:2(12): warning: int to float", &files());
+        assert_eq!(
+            diagnostics.to_string(),
+            "warning: shaders/common.glsl:2:12: int to float\n\
+             \x20 2 |     return 1;\n\
+             \x20   |            ^\n"
+        );
+    }
+
+    #[test]
+    fn display_keeps_tabs_in_caret_line() {
+        let diagnostics = Diagnostics::from_log("0:4(9): error: `foo' undeclared", &files());
+        assert_eq!(
+            diagnostics.to_string(),
+            "error: shaders/triangle.frag:4:9: `foo' undeclared\n\
+             \x20 4 | \tColor = foo;\n\
+             \x20   | \t       ^\n"
+        );
+    }
+}
@@ -0,0 +1,485 @@
+use super::{ColorBuffer, Filter, PixelFormat, Texture, TextureKind, Viewport};
+use crate::resources::{Image, ImageColor};
+use failure::Fail;
+use gl;
+use std::path::PathBuf;
+
+#[derive(Debug, Fail)]
+pub enum Error {
+    #[fail(display = "Framebuffer attachment is incomplete")]
+    IncompleteAttachment,
+    #[fail(display = "Framebuffer has no attachments")]
+    MissingAttachment,
+    #[fail(display = "Framebuffer draw buffer has no attachment")]
+    IncompleteDrawBuffer,
+    #[fail(display = "Framebuffer read buffer has no attachment")]
+    IncompleteReadBuffer,
+    #[fail(display = "Framebuffer attachment formats are not supported by the driver")]
+    Unsupported,
+    #[fail(display = "Framebuffer attachments have different sample counts")]
+    IncompleteMultisample,
+    #[fail(display = "Framebuffer mixes layered and non-layered attachments")]
+    IncompleteLayerTargets,
+    #[fail(display = "Framebuffer is incomplete, status 0x{:x}", status)]
+    Incomplete { status: gl::types::GLenum },
+}
+
+impl Error {
+    fn from_status(status: gl::types::GLenum) -> Self {
+        match status {
+            gl::FRAMEBUFFER_INCOMPLETE_ATTACHMENT => Error::IncompleteAttachment,
+            gl::FRAMEBUFFER_INCOMPLETE_MISSING_ATTACHMENT => Error::MissingAttachment,
+            gl::FRAMEBUFFER_INCOMPLETE_DRAW_BUFFER => Error::IncompleteDrawBuffer,
+            gl::FRAMEBUFFER_INCOMPLETE_READ_BUFFER => Error::IncompleteReadBuffer,
+            gl::FRAMEBUFFER_UNSUPPORTED => Error::Unsupported,
+            gl::FRAMEBUFFER_INCOMPLETE_MULTISAMPLE => Error::IncompleteMultisample,
+            gl::FRAMEBUFFER_INCOMPLETE_LAYER_TARGETS => Error::IncompleteLayerTargets,
+            status => Error::Incomplete { status },
+        }
+    }
+}
+
+// -=-=-=-=-=-=-=-=-=-=-=-=-=-=-=-=-=-=-=-=-=-=-=-=-=-=-=-
+
+/// Render target that is never sampled, typically the depth/stencil buffer
+/// of a framebuffer.
+pub struct Renderbuffer {
+    gl: gl::Gl,
+    id: gl::types::GLuint,
+    format: PixelFormat,
+    width: u32,
+    height: u32,
+}
+
+impl Renderbuffer {
+    pub fn new(gl: &gl::Gl, width: u32, height: u32, format: PixelFormat) -> Renderbuffer {
+        let mut id: gl::types::GLuint = 0;
+        unsafe {
+            gl.GenRenderbuffers(1, &mut id);
+            gl.BindRenderbuffer(gl::RENDERBUFFER, id);
+            gl.RenderbufferStorage(
+                gl::RENDERBUFFER,
+                format.internal_format(),
+                width as gl::types::GLsizei,
+                height as gl::types::GLsizei,
+            );
+            gl.BindRenderbuffer(gl::RENDERBUFFER, 0);
+        }
+        Renderbuffer {
+            gl: gl.clone(),
+            id,
+            format,
+            width,
+            height,
+        }
+    }
+
+    pub fn id(&self) -> gl::types::GLuint {
+        self.id
+    }
+
+    pub fn format(&self) -> PixelFormat {
+        self.format
+    }
+
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+
+    pub fn height(&self) -> u32 {
+        self.height
+    }
+}
+
+impl Drop for Renderbuffer {
+    fn drop(&mut self) {
+        unsafe {
+            self.gl.DeleteRenderbuffers(1, &self.id);
+        }
+    }
+}
+
+// -=-=-=-=-=-=-=-=-=-=-=-=-=-=-=-=-=-=-=-=-=-=-=-=-=-=-=-
+
+/// Depth or depth/stencil attachment of a `Framebuffer`. Use a texture when
+/// the depth needs to be sampled later, e.g. for shadow maps.
+pub enum DepthAttachment {
+    Texture(Texture),
+    Renderbuffer(Renderbuffer),
+}
+
+impl DepthAttachment {
+    fn format(&self) -> PixelFormat {
+        match self {
+            DepthAttachment::Texture(texture) => texture.format(),
+            DepthAttachment::Renderbuffer(renderbuffer) => renderbuffer.format(),
+        }
+    }
+
+    fn attachment_point(&self) -> gl::types::GLenum {
+        match self.format() {
+            PixelFormat::Depth24Stencil8 => gl::DEPTH_STENCIL_ATTACHMENT,
+            _ => gl::DEPTH_ATTACHMENT,
+        }
+    }
+}
+
+/// Offscreen render target. The framebuffer owns its attachments, so color
+/// textures can be borrowed for sampling in a later pass with `color`.
+///
+/// Only `bind`, `unbind` and `clear` change the framebuffer that is rendered
+/// to. The other functions use direct state access when available and
+/// otherwise restore the previous binding.
+pub struct Framebuffer {
+    gl: gl::Gl,
+    id: gl::types::GLuint,
+    dsa: bool,
+    viewport: Viewport,
+    colors: Vec<Texture>,
+    depth: Option<DepthAttachment>,
+}
+
+impl Framebuffer {
+    /// Framebuffer without attachments. All attachments need to be
+    /// `width` x `height`.
+    pub fn new(gl: &gl::Gl, width: u32, height: u32) -> Framebuffer {
+        let dsa = gl.has_direct_state_access();
+        let mut id: gl::types::GLuint = 0;
+        unsafe {
+            if dsa {
+                gl.CreateFramebuffers(1, &mut id);
+            } else {
+                gl.GenFramebuffers(1, &mut id);
+            }
+        }
+        Framebuffer {
+            gl: gl.clone(),
+            id,
+            dsa,
+            viewport: Viewport::for_window(width as i32, height as i32),
+            colors: Vec::new(),
+            depth: None,
+        }
+    }
+
+    /// The usual post-processing target: one color texture and a
+    /// depth/stencil renderbuffer, checked for completeness.
+    pub fn with_color_and_depth(
+        gl: &gl::Gl,
+        width: u32,
+        height: u32,
+        color_format: PixelFormat,
+    ) -> Result<Framebuffer, Error> {
+        let mut framebuffer = Framebuffer::new(gl, width, height);
+        framebuffer.attach_color(Texture::new_2d(gl, width, height, color_format));
+        framebuffer.attach_depth(DepthAttachment::Renderbuffer(Renderbuffer::new(
+            gl,
+            width,
+            height,
+            PixelFormat::Depth24Stencil8,
+        )));
+        framebuffer.check_complete()?;
+        Ok(framebuffer)
+    }
+
+    pub fn id(&self) -> gl::types::GLuint {
+        self.id
+    }
+
+    pub fn width(&self) -> u32 {
+        self.viewport.w as u32
+    }
+
+    pub fn height(&self) -> u32 {
+        self.viewport.h as u32
+    }
+
+    /// Attaches `texture` to the next color attachment point and returns its
+    /// index, which is also the fragment shader output location.
+    pub fn attach_color(&mut self, texture: Texture) -> usize {
+        assert_eq!(
+            texture.kind(),
+            TextureKind::Texture2D,
+            "color attachments need to be 2D textures"
+        );
+        self.check_size(texture.width(), texture.height());
+        let index = self.colors.len();
+        self.attach_texture(gl::COLOR_ATTACHMENT0 + index as gl::types::GLenum, &texture);
+        self.colors.push(texture);
+
+        let draw_buffers: Vec<gl::types::GLenum> = (0..self.colors.len())
+            .map(|i| gl::COLOR_ATTACHMENT0 + i as gl::types::GLenum)
+            .collect();
+        let count = draw_buffers.len() as gl::types::GLsizei;
+        if self.dsa {
+            unsafe {
+                self.gl
+                    .NamedFramebufferDrawBuffers(self.id, count, draw_buffers.as_ptr());
+            }
+        } else {
+            self.with_bound(gl::DRAW_FRAMEBUFFER, || unsafe {
+                self.gl.DrawBuffers(count, draw_buffers.as_ptr());
+            });
+        }
+        index
+    }
+
+    /// Sets the depth or depth/stencil attachment, replacing the previous one.
+    pub fn attach_depth(&mut self, depth: DepthAttachment) {
+        let attachment_point = depth.attachment_point();
+        // detach the old one in case it used the other attachment point
+        if let Some(ref old) = self.depth {
+            self.attach_renderbuffer(old.attachment_point(), 0);
+        }
+        match depth {
+            DepthAttachment::Texture(ref texture) => {
+                assert_eq!(
+                    texture.kind(),
+                    TextureKind::Texture2D,
+                    "depth attachments need to be 2D textures"
+                );
+                self.check_size(texture.width(), texture.height());
+                self.attach_texture(attachment_point, texture);
+            }
+            DepthAttachment::Renderbuffer(ref renderbuffer) => {
+                self.check_size(renderbuffer.width(), renderbuffer.height());
+                self.attach_renderbuffer(attachment_point, renderbuffer.id());
+            }
+        }
+        self.depth = Some(depth);
+    }
+
+    fn attach_texture(&self, attachment_point: gl::types::GLenum, texture: &Texture) {
+        if self.dsa {
+            unsafe {
+                self.gl
+                    .NamedFramebufferTexture(self.id, attachment_point, texture.id(), 0);
+            }
+        } else {
+            self.with_bound(gl::DRAW_FRAMEBUFFER, || unsafe {
+                self.gl.FramebufferTexture2D(
+                    gl::DRAW_FRAMEBUFFER,
+                    attachment_point,
+                    gl::TEXTURE_2D,
+                    texture.id(),
+                    0,
+                );
+            });
+        }
+    }
+
+    /// Attaches `renderbuffer`, or detaches `attachment_point` with 0.
+    fn attach_renderbuffer(
+        &self,
+        attachment_point: gl::types::GLenum,
+        renderbuffer: gl::types::GLuint,
+    ) {
+        if self.dsa {
+            unsafe {
+                self.gl.NamedFramebufferRenderbuffer(
+                    self.id,
+                    attachment_point,
+                    gl::RENDERBUFFER,
+                    renderbuffer,
+                );
+            }
+        } else {
+            self.with_bound(gl::DRAW_FRAMEBUFFER, || unsafe {
+                self.gl.FramebufferRenderbuffer(
+                    gl::DRAW_FRAMEBUFFER,
+                    attachment_point,
+                    gl::RENDERBUFFER,
+                    renderbuffer,
+                );
+            });
+        }
+    }
+
+    /// Binds the framebuffer to `target`, `gl::DRAW_FRAMEBUFFER` or
+    /// `gl::READ_FRAMEBUFFER`, while running `f` and restores the previous
+    /// binding of `target` afterwards.
+    fn with_bound<R, F: FnOnce() -> R>(&self, target: gl::types::GLenum, f: F) -> R {
+        let previous = bound_framebuffer(&self.gl, target);
+        unsafe {
+            self.gl.BindFramebuffer(target, self.id);
+        }
+        let result = f();
+        unsafe {
+            self.gl.BindFramebuffer(target, previous);
+        }
+        result
+    }
+
+    fn check_size(&self, width: u32, height: u32) {
+        assert!(
+            width == self.width() && height == self.height(),
+            "attachment is {}x{}, framebuffer is {}x{}",
+            width,
+            height,
+            self.width(),
+            self.height()
+        );
+    }
+
+    pub fn check_complete(&self) -> Result<(), Error> {
+        let status = if self.dsa {
+            unsafe {
+                self.gl
+                    .CheckNamedFramebufferStatus(self.id, gl::DRAW_FRAMEBUFFER)
+            }
+        } else {
+            self.with_bound(gl::DRAW_FRAMEBUFFER, || unsafe {
+                self.gl.CheckFramebufferStatus(gl::DRAW_FRAMEBUFFER)
+            })
+        };
+        if status == gl::FRAMEBUFFER_COMPLETE {
+            Ok(())
+        } else {
+            Err(Error::from_status(status))
+        }
+    }
+
+    /// Color attachment `index`, e.g. to bind it with `bind_to_unit` and
+    /// sample it in the next pass.
+    pub fn color(&self, index: usize) -> &Texture {
+        &self.colors[index]
+    }
+
+    pub fn depth(&self) -> Option<&DepthAttachment> {
+        self.depth.as_ref()
+    }
+
+    /// Reads back color attachment 0 as RGBA image with the rows in image
+    /// order, top to bottom, e.g. to save it with `Image::save_png`.
+    pub fn read_pixels(&self) -> Image {
+        let (width, height) = (self.width(), self.height());
+        let mut image = Image {
+            width,
+            height,
+            color: ImageColor::Rgba,
+            data: vec![0; width as usize * height as usize * 4],
+            path: PathBuf::new(),
+        };
+        // glReadPixels has no DSA variant
+        self.with_bound(gl::READ_FRAMEBUFFER, || unsafe {
+            let mut alignment: gl::types::GLint = 0;
+            self.gl.GetIntegerv(gl::PACK_ALIGNMENT, &mut alignment);
+            self.gl.ReadBuffer(gl::COLOR_ATTACHMENT0);
+            self.gl.PixelStorei(gl::PACK_ALIGNMENT, 1);
+            self.gl.ReadPixels(
+                0,
+                0,
+                width as gl::types::GLsizei,
+                height as gl::types::GLsizei,
+                gl::RGBA,
+                gl::UNSIGNED_BYTE,
+                image.data.as_mut_ptr() as *mut gl::types::GLvoid,
+            );
+            self.gl.PixelStorei(gl::PACK_ALIGNMENT, alignment);
+        });
+        image.flip_vertically();
+        image
+    }
+
+    /// Renders into this framebuffer and sets the viewport to its size.
+    pub fn bind(&self) {
+        unsafe {
+            self.gl.BindFramebuffer(gl::FRAMEBUFFER, self.id);
+        }
+        self.viewport.set_used(&self.gl);
+    }
+
+    /// Renders into the window again and restores its `viewport`.
+    pub fn unbind(&self, viewport: &Viewport) {
+        unsafe {
+            self.gl.BindFramebuffer(gl::FRAMEBUFFER, 0);
+        }
+        viewport.set_used(&self.gl);
+    }
+
+    /// Binds the framebuffer and clears all of its attachments, color with
+    /// `color_buffer`, depth to 1.0 and stencil to 0.
+    pub fn clear(&self, color_buffer: &ColorBuffer) {
+        self.bind();
+        let mut mask = gl::COLOR_BUFFER_BIT;
+        match self.depth.as_ref().map(|depth| depth.attachment_point()) {
+            Some(gl::DEPTH_STENCIL_ATTACHMENT) => {
+                mask |= gl::DEPTH_BUFFER_BIT | gl::STENCIL_BUFFER_BIT
+            }
+            Some(_) => mask |= gl::DEPTH_BUFFER_BIT,
+            None => {}
+        }
+        color_buffer.set_used(&self.gl);
+        unsafe {
+            self.gl.Clear(mask);
+        }
+    }
+
+    /// Copies the buffers selected by `mask` (`gl::COLOR_BUFFER_BIT` etc.)
+    /// into `target`, or into the window when `target` is `None`, scaling to
+    /// `viewport`. Color is read from attachment 0; depth and stencil can only
+    /// be copied with `Filter::Nearest`.
+    pub fn blit_to(
+        &self,
+        target: Option<&Framebuffer>,
+        viewport: &Viewport,
+        mask: gl::types::GLbitfield,
+        filter: Filter,
+    ) {
+        let target = target.map(|t| t.id).unwrap_or(0);
+        let (w, h) = (self.viewport.w, self.viewport.h);
+        let (x0, y0) = (viewport.x, viewport.y);
+        let (x1, y1) = (viewport.x + viewport.w, viewport.y + viewport.h);
+        if self.dsa {
+            unsafe {
+                self.gl
+                    .NamedFramebufferReadBuffer(self.id, gl::COLOR_ATTACHMENT0);
+                self.gl.BlitNamedFramebuffer(
+                    self.id,
+                    target,
+                    0,
+                    0,
+                    w,
+                    h,
+                    x0,
+                    y0,
+                    x1,
+                    y1,
+                    mask,
+                    filter.gl_enum(),
+                );
+            }
+            return;
+        }
+        let previous_draw = bound_framebuffer(&self.gl, gl::DRAW_FRAMEBUFFER);
+        self.with_bound(gl::READ_FRAMEBUFFER, || unsafe {
+            self.gl.ReadBuffer(gl::COLOR_ATTACHMENT0);
+            self.gl.BindFramebuffer(gl::DRAW_FRAMEBUFFER, target);
+            self.gl
+                .BlitFramebuffer(0, 0, w, h, x0, y0, x1, y1, mask, filter.gl_enum());
+            self.gl.BindFramebuffer(gl::DRAW_FRAMEBUFFER, previous_draw);
+        });
+    }
+}
+
+/// Framebuffer bound to `target`, `gl::DRAW_FRAMEBUFFER` or
+/// `gl::READ_FRAMEBUFFER`.
+fn bound_framebuffer(gl: &gl::Gl, target: gl::types::GLenum) -> gl::types::GLuint {
+    let binding = match target {
+        gl::READ_FRAMEBUFFER => gl::READ_FRAMEBUFFER_BINDING,
+        _ => gl::DRAW_FRAMEBUFFER_BINDING,
+    };
+    let mut framebuffer: gl::types::GLint = 0;
+    unsafe {
+        gl.GetIntegerv(binding, &mut framebuffer);
+    }
+    framebuffer as gl::types::GLuint
+}
+
+impl Drop for Framebuffer {
+    fn drop(&mut self) {
+        unsafe {
+            self.gl.DeleteFramebuffers(1, &self.id);
+        }
+    }
+}
@@ -0,0 +1,189 @@
+use super::buffer::{ArrayBuffer, IndexBuffer, IndexType, VertexArray};
+use super::{Error, Program, Vertex, VertexAttrib};
+use gl;
+use std::any::TypeId;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Topology {
+    Points,
+    Lines,
+    LineStrip,
+    LineLoop,
+    Triangles,
+    TriangleStrip,
+    TriangleFan,
+}
+
+impl Topology {
+    pub fn gl_enum(self) -> gl::types::GLenum {
+        match self {
+            Topology::Points => gl::POINTS,
+            Topology::Lines => gl::LINES,
+            Topology::LineStrip => gl::LINE_STRIP,
+            Topology::LineLoop => gl::LINE_LOOP,
+            Topology::Triangles => gl::TRIANGLES,
+            Topology::TriangleStrip => gl::TRIANGLE_STRIP,
+            Topology::TriangleFan => gl::TRIANGLE_FAN,
+        }
+    }
+}
+
+/// Per-instance vertex buffer of a `Mesh`.
+struct Instances {
+    vbo: ArrayBuffer,
+    count: usize,
+    layout: Vec<VertexAttrib>,
+    /// vertex type the attributes were set up for
+    type_id: TypeId,
+    type_name: &'static str,
+}
+
+/// Vertex array with its vertex buffer and optional index buffer, drawn with
+/// a fixed topology. With `set_instances` it is drawn once per instance.
+pub struct Mesh<V, I = u32>
+where
+    V: Vertex,
+    I: IndexType,
+{
+    gl: gl::Gl,
+    vao: VertexArray,
+    vbo: ArrayBuffer,
+    ebo: Option<IndexBuffer<I>>,
+    topology: Topology,
+    instances: Option<Instances>,
+    _marker: ::std::marker::PhantomData<V>,
+}
+
+impl<V> Mesh<V>
+where
+    V: Vertex,
+{
+    /// Mesh drawn with `glDrawArrays`.
+    pub fn new(gl: &gl::Gl, vertices: &[V], topology: Topology) -> Self {
+        Self::build(gl, vertices, None, topology)
+    }
+}
+
+impl<V, I> Mesh<V, I>
+where
+    V: Vertex,
+    I: IndexType,
+{
+    /// Mesh drawn with `glDrawElements`.
+    pub fn indexed(gl: &gl::Gl, vertices: &[V], indices: &[I], topology: Topology) -> Self {
+        Self::build(gl, vertices, Some(indices), topology)
+    }
+
+    fn build(gl: &gl::Gl, vertices: &[V], indices: Option<&[I]>, topology: Topology) -> Self {
+        let vao = VertexArray::new(gl);
+        let vbo = ArrayBuffer::new(gl);
+        vbo.static_draw(vertices);
+        vao.set_vertex_buffer::<V>(&vbo);
+
+        let ebo = indices.map(|indices| {
+            let ebo = IndexBuffer::new(gl);
+            ebo.static_draw(indices);
+            vao.set_index_buffer(&ebo);
+            ebo
+        });
+
+        Mesh {
+            gl: gl.clone(),
+            vao,
+            vbo,
+            ebo,
+            topology,
+            instances: None,
+            _marker: ::std::marker::PhantomData,
+        }
+    }
+
+    pub fn topology(&self) -> Topology {
+        self.topology
+    }
+
+    /// Number of vertices, or of indices for an indexed mesh.
+    pub fn len(&self) -> usize {
+        match self.ebo {
+            Some(ref ebo) => ebo.len(),
+            None => self.vbo.len(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Checks that the inputs of `program` match the vertex layout of `V` and
+    /// of the instance data.
+    pub fn check_program(&self, program: &Program) -> Result<(), Error> {
+        let mut layout = V::vertex_layout();
+        if let Some(ref instances) = self.instances {
+            layout.extend_from_slice(&instances.layout);
+        }
+        program.check_vertex_layout(&layout)
+    }
+
+    /// Attaches a second vertex buffer with one `J` per instance, `J` usually
+    /// being a `#[divisor = 1]` vertex struct whose locations follow those of
+    /// `V`. From now on `draw` draws `instances.len()` instances. Calling it
+    /// again replaces the instance data; panics if `J` differs from the
+    /// first call, whose attribute layout the vertex array keeps.
+    pub fn set_instances<J: Vertex>(&mut self, instances: &[J]) {
+        match self.instances {
+            Some(ref mut existing) => {
+                assert!(
+                    existing.type_id == TypeId::of::<J>(),
+                    "instances were set up as {}, not {}",
+                    existing.type_name,
+                    std::any::type_name::<J>()
+                );
+                existing.vbo.update(instances);
+                existing.count = instances.len();
+            }
+            None => {
+                let vbo = ArrayBuffer::new(&self.gl);
+                vbo.dynamic_draw(instances);
+                self.vao.set_vertex_buffer::<J>(&vbo);
+                self.instances = Some(Instances {
+                    vbo,
+                    count: instances.len(),
+                    layout: J::vertex_layout(),
+                    type_id: TypeId::of::<J>(),
+                    type_name: std::any::type_name::<J>(),
+                });
+            }
+        }
+    }
+
+    /// Number of instances drawn, `None` without instance data.
+    pub fn instance_count(&self) -> Option<usize> {
+        self.instances.as_ref().map(|instances| instances.count)
+    }
+
+    /// Replaces the vertices, e.g. for animated geometry. The vertex count of
+    /// an indexed mesh should stay the same.
+    pub fn update_vertices(&self, vertices: &[V]) {
+        self.vbo.update(vertices);
+    }
+
+    /// Draws with `program`. The program and vertex array stay bound, so
+    /// drawing the same mesh again does not switch them.
+    pub fn draw(&self, program: &Program) {
+        let mode = self.topology.gl_enum();
+        let vertex_count = self.vbo.len() as gl::types::GLsizei;
+        program.set_used();
+        self.vao.bind();
+        match (&self.ebo, self.instance_count()) {
+            (Some(ebo), None) => ebo.draw(mode),
+            (Some(ebo), Some(instances)) => ebo.draw_instanced(mode, instances),
+            (None, None) => unsafe {
+                self.gl.DrawArrays(mode, 0, vertex_count);
+            },
+            (None, Some(instances)) => unsafe {
+                self.gl
+                    .DrawArraysInstanced(mode, 0, vertex_count, instances as gl::types::GLsizei);
+            },
+        }
+    }
+}
@@ -0,0 +1,362 @@
+use super::Error;
+use crate::resources::Resources;
+use std::ffi::CString;
+use std::path::PathBuf;
+
+/// A file that went into a preprocessed shader.
+#[derive(Debug, Clone)]
+pub struct SourceFile {
+    pub name: String,
+    pub path: PathBuf,
+    pub content: String,
+}
+
+/// GLSL source with all `#include "..."` directives resolved.
+#[derive(Debug)]
+pub struct PreprocessedSource {
+    pub content: CString,
+    /// Every file that went into `content`. The index of a file is the source
+    /// string number used in the `#line` directives, the top-level file is 0.
+    pub files: Vec<SourceFile>,
+}
+
+impl PreprocessedSource {
+    pub fn paths(&self) -> Vec<PathBuf> {
+        self.files.iter().map(|f| f.path.clone()).collect()
+    }
+}
+
+/// Loads the resource `name` and recursively replaces lines of the form
+/// `#include "path"` with the content of that resource. Include paths are
+/// relative to the including file, or to the resource root when they start
+/// with `/`.
+///
+/// Every file is included at most once per shader, so headers do not need
+/// include guards; including a file that is currently being processed is
+/// reported as `Error::IncludeCycle`. `#line` directives are emitted around
+/// each inclusion so that compiler messages refer to the original file and
+/// line.
+///
+/// `defines` are injected right after the `#version` line of the top-level
+/// file, each either `NAME` or `NAME=VALUE`.
+pub fn preprocess(
+    res: &Resources,
+    name: &str,
+    defines: &[&str],
+) -> Result<PreprocessedSource, Error> {
+    let mut preprocessor = Preprocessor {
+        res,
+        files: Vec::new(),
+        stack: Vec::new(),
+        output: String::new(),
+        defines: defines
+            .iter()
+            .map(|define| match define.find('=') {
+                Some(i) => format!("#define {} {}\n", &define[..i], &define[i + 1..]),
+                None => format!("#define {}\n", define),
+            })
+            .collect(),
+    };
+    preprocessor.process_file(name)?;
+
+    let Preprocessor { files, output, .. } = preprocessor;
+    let content = CString::new(output).map_err(|_| Error::ResourceLoad {
+        name: name.into(),
+        inner: crate::resources::Error::FileContainsNil,
+    })?;
+    Ok(PreprocessedSource { content, files })
+}
+
+struct Preprocessor<'a> {
+    res: &'a Resources,
+    files: Vec<SourceFile>,
+    stack: Vec<String>,
+    output: String,
+    defines: String,
+}
+
+impl<'a> Preprocessor<'a> {
+    fn process_file(&mut self, name: &str) -> Result<(), Error> {
+        if self.stack.iter().any(|n| n == name) {
+            let mut chain = self.stack.clone();
+            chain.push(name.into());
+            return Err(Error::IncludeCycle {
+                chain: chain.join(" -> "),
+            });
+        }
+        if self.files.iter().any(|f| f.name == name) {
+            return Ok(());
+        }
+
+        let source = self.res.load_cstring(name).map_err(|e| Error::ResourceLoad {
+            name: name.into(),
+            inner: e,
+        })?;
+        let content = source
+            .content
+            .into_string()
+            .map_err(|_| Error::SourceNotUtf8 { name: name.into() })?;
+
+        let index = self.files.len();
+        self.files.push(SourceFile {
+            name: name.into(),
+            path: source.path,
+            content: content.clone(),
+        });
+        self.stack.push(name.into());
+
+        let is_root = index == 0;
+        let has_version = content
+            .lines()
+            .any(|line| line.trim_start().starts_with("#version"));
+        if !is_root {
+            self.emit_line_directive(1, index);
+        } else if !has_version {
+            self.emit_defines(1, index);
+        }
+
+        for (i, line) in content.lines().enumerate() {
+            let line_no = i + 1;
+            let directive = line.trim_start();
+            if directive.starts_with("#version") {
+                if !is_root {
+                    return Err(Error::Preprocess {
+                        name: name.into(),
+                        line: line_no,
+                        message: "#version is only allowed in the top-level shader".into(),
+                    });
+                }
+                self.output.push_str(line);
+                self.output.push('\n');
+                self.emit_defines(line_no + 1, index);
+            } else if directive.starts_with("#include") {
+                let include = parse_include(directive).ok_or_else(|| Error::Preprocess {
+                    name: name.into(),
+                    line: line_no,
+                    message: format!("expected #include \"path\", got {}", directive),
+                })?;
+                let include_name = resolve_include(name, include);
+                self.process_file(&include_name)?;
+                self.emit_line_directive(line_no + 1, index);
+            } else {
+                self.output.push_str(line);
+                self.output.push('\n');
+            }
+        }
+
+        self.stack.pop();
+        Ok(())
+    }
+
+    fn emit_defines(&mut self, next_line: usize, source_index: usize) {
+        if !self.defines.is_empty() {
+            self.output.push_str(&self.defines);
+            self.emit_line_directive(next_line, source_index);
+        }
+    }
+
+    /// Since GLSL 3.30 `#line n` sets the number of the line that follows it.
+    fn emit_line_directive(&mut self, next_line: usize, source_index: usize) {
+        self.output
+            .push_str(&format!("#line {} {}\n", next_line, source_index));
+    }
+}
+
+fn parse_include(directive: &str) -> Option<&str> {
+    let rest = directive["#include".len()..].trim();
+    if rest.len() >= 2 && rest.starts_with('"') && rest.ends_with('"') {
+        Some(&rest[1..rest.len() - 1])
+    } else {
+        None
+    }
+}
+
+fn resolve_include(including: &str, include: &str) -> String {
+    let mut parts: Vec<&str> = if include.starts_with('/') {
+        Vec::new()
+    } else {
+        let mut dir: Vec<&str> = including.split('/').collect();
+        dir.pop();
+        dir
+    };
+    for part in include.split('/') {
+        match part {
+            "" | "." => {}
+            ".." => {
+                parts.pop();
+            }
+            part => parts.push(part),
+        }
+    }
+    parts.join("/")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use std::path::Path;
+
+    /// Resources in a fresh temp directory holding `files`.
+    fn resources(test: &str, files: &[(&str, &str)]) -> Resources {
+        let root =
+            std::env::temp_dir().join(format!("preprocessor_{}_{}", test, std::process::id()));
+        let _ = fs::remove_dir_all(&root);
+        for (name, content) in files {
+            let path = root.join(name);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, content).unwrap();
+        }
+        Resources::from_path(&root)
+    }
+
+    fn output(source: &PreprocessedSource) -> &str {
+        source.content.to_str().unwrap()
+    }
+
+    #[test]
+    fn resolve_relative_and_absolute() {
+        assert_eq!(
+            resolve_include("shaders/a.frag", "common.glsl"),
+            "shaders/common.glsl"
+        );
+        assert_eq!(
+            resolve_include("shaders/a.frag", "./lib/b.glsl"),
+            "shaders/lib/b.glsl"
+        );
+        assert_eq!(
+            resolve_include("shaders/a.frag", "../lib/b.glsl"),
+            "lib/b.glsl"
+        );
+        assert_eq!(
+            resolve_include("shaders/x/a.frag", "../../b.glsl"),
+            "b.glsl"
+        );
+        assert_eq!(
+            resolve_include("shaders/a.frag", "/lib/b.glsl"),
+            "lib/b.glsl"
+        );
+        assert_eq!(resolve_include("a.frag", "b.glsl"), "b.glsl");
+    }
+
+    #[test]
+    fn parse_include_needs_quotes() {
+        assert_eq!(parse_include("#include \"a.glsl\""), Some("a.glsl"));
+        assert_eq!(parse_include("#include   \"a.glsl\"  "), Some("a.glsl"));
+        assert_eq!(parse_include("#include <a.glsl>"), None);
+        assert_eq!(parse_include("#include \""), None);
+    }
+
+    #[test]
+    fn defines_and_line_directives_follow_version() {
+        let res = resources(
+            "version",
+            &[
+                (
+                    "shaders/a.frag",
+                    "#version 330 core\n#include \"lib.glsl\"\nvoid main() {}\n",
+                ),
+                ("shaders/lib.glsl", "float f();\n"),
+            ],
+        );
+        let source = preprocess(&res, "shaders/a.frag", &["FOG", "LIGHTS=4"]).unwrap();
+        assert_eq!(
+            output(&source),
+            "#version 330 core\n\
+             #define FOG\n\
+             #define LIGHTS 4\n\
+             #line 2 0\n\
+             #line 1 1\n\
+             float f();\n\
+             #line 3 0\n\
+             void main() {}\n"
+        );
+        let names: Vec<&str> = source.files.iter().map(|f| f.name.as_str()).collect();
+        assert_eq!(names, ["shaders/a.frag", "shaders/lib.glsl"]);
+        assert_eq!(
+            source.files[1].path,
+            Path::new(&std::env::temp_dir())
+                .join(format!("preprocessor_version_{}", std::process::id()))
+                .join("shaders/lib.glsl")
+        );
+    }
+
+    #[test]
+    fn defines_without_version_go_first() {
+        let res = resources("no_version", &[("a.vert", "void main() {}\n")]);
+        let source = preprocess(&res, "a.vert", &["A"]).unwrap();
+        assert_eq!(output(&source), "#define A\n#line 1 0\nvoid main() {}\n");
+    }
+
+    #[test]
+    fn files_are_included_once() {
+        let res = resources(
+            "once",
+            &[
+                ("a.frag", "#include \"b.glsl\"\n#include \"c.glsl\"\n"),
+                ("b.glsl", "#include \"/common.glsl\"\nb\n"),
+                ("c.glsl", "#include \"common.glsl\"\nc\n"),
+                ("common.glsl", "common\n"),
+            ],
+        );
+        let source = preprocess(&res, "a.frag", &[]).unwrap();
+        assert_eq!(output(&source).matches("common").count(), 1);
+        let names: Vec<&str> = source.files.iter().map(|f| f.name.as_str()).collect();
+        assert_eq!(names, ["a.frag", "b.glsl", "common.glsl", "c.glsl"]);
+    }
+
+    #[test]
+    fn include_cycle_is_an_error() {
+        let res = resources(
+            "cycle",
+            &[
+                ("a.frag", "#include \"b.glsl\"\n"),
+                ("b.glsl", "#include \"c.glsl\"\n"),
+                ("c.glsl", "#include \"b.glsl\"\n"),
+            ],
+        );
+        match preprocess(&res, "a.frag", &[]) {
+            Err(Error::IncludeCycle { chain }) => {
+                assert_eq!(chain, "a.frag -> b.glsl -> c.glsl -> b.glsl")
+            }
+            other => panic!("expected an include cycle, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn version_in_include_is_an_error() {
+        let res = resources(
+            "nested_version",
+            &[
+                ("a.frag", "#version 330 core\n#include \"b.glsl\"\n"),
+                ("b.glsl", "\n#version 330 core\n"),
+            ],
+        );
+        match preprocess(&res, "a.frag", &[]) {
+            Err(Error::Preprocess { name, line, .. }) => {
+                assert_eq!((name.as_str(), line), ("b.glsl", 2))
+            }
+            other => panic!("expected a preprocess error, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn malformed_include_is_an_error() {
+        let res = resources("malformed", &[("a.frag", "x\n#include <b.glsl>\n")]);
+        match preprocess(&res, "a.frag", &[]) {
+            Err(Error::Preprocess { name, line, .. }) => {
+                assert_eq!((name.as_str(), line), ("a.frag", 2))
+            }
+            other => panic!("expected a preprocess error, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn missing_include_is_a_resource_error() {
+        let res = resources("missing", &[("a.frag", "#include \"b.glsl\"\n")]);
+        match preprocess(&res, "a.frag", &[]) {
+            Err(Error::ResourceLoad { name, .. }) => assert_eq!(name, "b.glsl"),
+            other => panic!("expected a resource error, got {:?}", other),
+        }
+    }
+}
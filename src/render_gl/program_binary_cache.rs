@@ -0,0 +1,226 @@
+use super::capabilities;
+use super::shader::ShaderSource;
+use crate::resources;
+use gl;
+use std::ffi::CStr;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// Directory of linked program binaries (`glGetProgramBinary`), so that
+/// programs don't need to be compiled and linked again on every start.
+///
+/// Entries are keyed by the preprocessed sources of all stages plus the
+/// vendor, renderer and version strings of the driver, see
+/// `ProgramBinaryKey`. A binary that does not fit anymore is simply not found;
+/// one that the driver rejects anyway is recompiled and overwritten.
+pub struct ProgramBinaryCache {
+    dir: PathBuf,
+}
+
+impl ProgramBinaryCache {
+    pub fn new(dir: &Path) -> Result<ProgramBinaryCache, resources::Error> {
+        fs::create_dir_all(dir)?;
+        Ok(ProgramBinaryCache {
+            dir: dir.to_path_buf(),
+        })
+    }
+
+    /// Cache directory next to the executable, i.e. next to the `assets`
+    /// directory copied by `build.rs`.
+    pub fn from_relative_exe_path(path: &Path) -> Result<ProgramBinaryCache, resources::Error> {
+        let exec_file_name =
+            ::std::env::current_exe().map_err(|_| resources::Error::FaildToGetExecPath)?;
+        let exe_path = exec_file_name
+            .parent()
+            .ok_or(resources::Error::FaildToGetExecPath)?;
+        Self::new(&exe_path.join(path))
+    }
+
+    /// Program binaries need GL 4.1 and a driver that supports at least one
+    /// binary format.
+    pub fn is_supported(gl: &gl::Gl) -> bool {
+        if capabilities::gl_version(gl) < (4, 1) {
+            return false;
+        }
+        let mut formats: gl::types::GLint = 0;
+        unsafe {
+            gl.GetIntegerv(gl::NUM_PROGRAM_BINARY_FORMATS, &mut formats);
+        }
+        formats > 0
+    }
+
+    pub fn key(gl: &gl::Gl, sources: &[ShaderSource]) -> ProgramBinaryKey {
+        let mut key = ProgramBinaryKey {
+            material: Vec::new(),
+        };
+        for &name in &[gl::VENDOR, gl::RENDERER, gl::VERSION] {
+            key.push(gl_string(gl, name).as_bytes());
+        }
+        for source in sources {
+            key.push(&source.kind.to_le_bytes());
+            key.push(source.source.content.as_bytes());
+        }
+        key
+    }
+
+    fn path(&self, key: &ProgramBinaryKey) -> PathBuf {
+        self.dir.join(format!("{:016x}.bin", key.hash()))
+    }
+
+    /// Binary format and binary stored for `key`. Files written for other
+    /// keys with the same hash are ignored.
+    pub fn load(&self, key: &ProgramBinaryKey) -> Option<(gl::types::GLenum, Vec<u8>)> {
+        let content = fs::read(self.path(key)).ok()?;
+        let rest = content.strip_prefix(MAGIC)?;
+        let rest = rest.strip_prefix(&(key.material.len() as u64).to_le_bytes()[..])?;
+        let rest = rest.strip_prefix(&key.material[..])?;
+        if rest.len() < 4 {
+            return None;
+        }
+        let (format, binary) = rest.split_at(4);
+        let format = gl::types::GLenum::from_le_bytes([format[0], format[1], format[2], format[3]]);
+        Some((format, binary.to_vec()))
+    }
+
+    /// Writes `binary` with a header of the full `key`.
+    pub fn store(
+        &self,
+        key: &ProgramBinaryKey,
+        format: gl::types::GLenum,
+        binary: &[u8],
+    ) -> io::Result<()> {
+        let mut content: Vec<u8> =
+            Vec::with_capacity(MAGIC.len() + 8 + key.material.len() + 4 + binary.len());
+        content.extend_from_slice(MAGIC);
+        content.extend_from_slice(&(key.material.len() as u64).to_le_bytes());
+        content.extend_from_slice(&key.material);
+        content.extend_from_slice(&format.to_le_bytes());
+        content.extend_from_slice(binary);
+        fs::write(self.path(key), content)
+    }
+
+    /// Removes all stored binaries.
+    pub fn clear(&self) -> io::Result<()> {
+        for entry in fs::read_dir(&self.dir)? {
+            let path = entry?.path();
+            if path.extension().map(|ext| ext == "bin").unwrap_or(false) {
+                fs::remove_file(path)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Start of every cache file, the number is the file format version.
+const MAGIC: &[u8] = b"GLPB0001";
+
+/// Everything a program binary depends on: the driver strings and the kind
+/// and preprocessed source of every stage. Files are named by its FNV-1a
+/// hash, which unlike `DefaultHasher` does not change between Rust releases.
+pub struct ProgramBinaryKey {
+    material: Vec<u8>,
+}
+
+impl ProgramBinaryKey {
+    /// Appends `bytes` with its length, so that different splits of the same
+    /// bytes give different keys.
+    fn push(&mut self, bytes: &[u8]) {
+        self.material
+            .extend_from_slice(&(bytes.len() as u64).to_le_bytes());
+        self.material.extend_from_slice(bytes);
+    }
+
+    /// 64 bit FNV-1a hash of the key.
+    pub fn hash(&self) -> u64 {
+        self.material
+            .iter()
+            .fold(0xcbf2_9ce4_8422_2325, |hash, &byte| {
+                (hash ^ u64::from(byte)).wrapping_mul(0x0000_0100_0000_01b3)
+            })
+    }
+}
+
+fn gl_string(gl: &gl::Gl, name: gl::types::GLenum) -> String {
+    unsafe {
+        let s = gl.GetString(name);
+        if s.is_null() {
+            return String::new();
+        }
+        CStr::from_ptr(s as *const std::os::raw::c_char)
+            .to_string_lossy()
+            .into_owned()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn key(material: &[u8]) -> ProgramBinaryKey {
+        ProgramBinaryKey {
+            material: material.to_vec(),
+        }
+    }
+
+    fn cache(name: &str) -> ProgramBinaryCache {
+        let dir = std::env::temp_dir().join(format!(
+            "program_binary_cache_{}_{}",
+            name,
+            std::process::id()
+        ));
+        let _ = fs::remove_dir_all(&dir);
+        ProgramBinaryCache::new(&dir).unwrap()
+    }
+
+    #[test]
+    fn hash_is_fnv1a() {
+        assert_eq!(key(b"").hash(), 0xcbf2_9ce4_8422_2325);
+        assert_eq!(key(b"a").hash(), 0xaf63_dc4c_8601_ec8c);
+        assert_eq!(key(b"foobar").hash(), 0x8594_4171_f739_67e8);
+    }
+
+    #[test]
+    fn push_separates_fields() {
+        let mut ab = key(b"");
+        ab.push(b"ab");
+        ab.push(b"");
+        let mut a_b = key(b"");
+        a_b.push(b"a");
+        a_b.push(b"b");
+        assert_ne!(ab.material, a_b.material);
+    }
+
+    #[test]
+    fn store_and_load() {
+        let cache = cache("roundtrip");
+        let key = key(b"vendor renderer version source");
+        assert_eq!(cache.load(&key), None);
+        cache.store(&key, 0x8e21, &[1, 2, 3]).unwrap();
+        assert_eq!(cache.load(&key), Some((0x8e21, vec![1, 2, 3])));
+        cache.clear().unwrap();
+        assert_eq!(cache.load(&key), None);
+    }
+
+    #[test]
+    fn other_key_with_same_file_is_not_loaded() {
+        let cache = cache("collision");
+        let stored = key(b"stored");
+        let other = key(b"other");
+        cache.store(&stored, 1, &[1, 2, 3]).unwrap();
+        // as if both keys had the same hash
+        fs::copy(cache.path(&stored), cache.path(&other)).unwrap();
+        assert_eq!(cache.load(&other), None);
+        assert_eq!(cache.load(&stored), Some((1, vec![1, 2, 3])));
+    }
+
+    #[test]
+    fn truncated_file_is_not_loaded() {
+        let cache = cache("truncated");
+        let key = key(b"key");
+        cache.store(&key, 1, &[]).unwrap();
+        let content = fs::read(cache.path(&key)).unwrap();
+        fs::write(cache.path(&key), &content[..content.len() - 1]).unwrap();
+        assert_eq!(cache.load(&key), None);
+    }
+}
@@ -0,0 +1,153 @@
+use super::{Error, Program, ProgramBinaryCache};
+use crate::resources::{absolute_path, Reloadable, Resources};
+use failure;
+use gl;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+
+/// Shader resource name plus the sorted, deduplicated set of defines.
+type VariantKey = (String, Vec<String>);
+
+/// Shares linked programs per (shader name, define set) so that every variant
+/// is only compiled once.
+///
+/// Entries are dropped when one of their files changes: either register the
+/// cache with `ResourceWatcher::add_reloadable`, which `reload_changed` then
+/// tells about the changed file, or call `invalidate` with the changed path.
+/// `get_paths` grows when `get` builds variants from new files, so call
+/// `add_reloadable` again after that. Programs handed out before stay valid,
+/// the next `get` compiles the variant again.
+pub struct ProgramCache {
+    gl: gl::Gl,
+    programs: HashMap<VariantKey, Rc<Program>>,
+    paths: Vec<PathBuf>,
+    binary_cache: Option<ProgramBinaryCache>,
+}
+
+impl ProgramCache {
+    pub fn new(gl: &gl::Gl) -> Self {
+        ProgramCache {
+            gl: gl.clone(),
+            programs: HashMap::new(),
+            paths: Vec::new(),
+            binary_cache: None,
+        }
+    }
+
+    /// Variants that are not in memory yet are looked up in `binary_cache`
+    /// before compiling them.
+    pub fn with_binary_cache(gl: &gl::Gl, binary_cache: ProgramBinaryCache) -> Self {
+        ProgramCache {
+            binary_cache: Some(binary_cache),
+            ..Self::new(gl)
+        }
+    }
+
+    /// Returns the cached variant of `name` with `defines` (see
+    /// `Program::from_res_with_defines`) or compiles it. The order of
+    /// `defines` does not matter.
+    pub fn get(
+        &mut self,
+        res: &Resources,
+        name: &str,
+        defines: &[&str],
+    ) -> Result<Rc<Program>, Error> {
+        let key = variant_key(name, defines);
+        if let Some(program) = self.programs.get(&key) {
+            return Ok(program.clone());
+        }
+
+        let defines: Vec<&str> = key.1.iter().map(|d| d.as_str()).collect();
+        let program = Rc::new(match self.binary_cache {
+            Some(ref binary_cache) => {
+                Program::from_res_with_binary_cache(&self.gl, res, name, &defines, binary_cache)?
+            }
+            None => Program::from_res_with_defines(&self.gl, res, name, &defines)?,
+        });
+        for path in &program.paths {
+            if !self.paths.contains(path) {
+                self.paths.push(path.clone());
+            }
+        }
+        self.programs.insert(key, program.clone());
+        Ok(program)
+    }
+
+    /// Drops all variants built from the file at `path`. Returns how many
+    /// were dropped. Relative paths are taken from the current directory, as
+    /// `ResourceWatcher` does.
+    pub fn invalidate(&mut self, path: &Path) -> usize {
+        let path = absolute_path(path).unwrap_or_else(|_| path.to_path_buf());
+        let before = self.programs.len();
+        self.programs.retain(|_, program| {
+            !program
+                .paths
+                .iter()
+                .any(|p| absolute_path(p).is_ok_and(|p| p == path))
+        });
+        before - self.programs.len()
+    }
+
+    pub fn clear(&mut self) {
+        self.programs.clear();
+    }
+
+    pub fn len(&self) -> usize {
+        self.programs.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.programs.is_empty()
+    }
+}
+
+/// Key of `name` with `defines`, independent of their order and duplicates.
+fn variant_key(name: &str, defines: &[&str]) -> VariantKey {
+    let mut define_set: Vec<String> = defines.iter().map(|d| d.to_string()).collect();
+    define_set.sort();
+    define_set.dedup();
+    (name.to_string(), define_set)
+}
+
+impl Reloadable for ProgramCache {
+    /// Without the changed file all variants are dropped.
+    fn reload(&mut self, _gl: &gl::Gl, _res: &Resources) -> Result<(), failure::Error> {
+        self.clear();
+        Ok(())
+    }
+
+    fn reload_changed(
+        &mut self,
+        _gl: &gl::Gl,
+        _res: &Resources,
+        path: &Path,
+    ) -> Result<(), failure::Error> {
+        self.invalidate(path);
+        Ok(())
+    }
+
+    fn get_paths(&self) -> &[PathBuf] {
+        &self.paths
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn define_order_and_duplicates_give_the_same_key() {
+        let key = variant_key("shaders/triangle", &["B", "A=1", "B"]);
+        assert_eq!(
+            key,
+            (
+                "shaders/triangle".to_string(),
+                vec!["A=1".to_string(), "B".to_string()]
+            )
+        );
+        assert_eq!(variant_key("shaders/triangle", &["A=1", "B"]), key);
+        assert_ne!(variant_key("shaders/triangle", &["A=2", "B"]), key);
+        assert_ne!(variant_key("shaders/rectangle", &["A=1", "B"]), key);
+    }
+}
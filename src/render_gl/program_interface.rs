@@ -0,0 +1,213 @@
+use gl;
+use std::fmt;
+
+/// An active attribute or uniform of a linked program.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ActiveVariable {
+    pub name: String,
+    /// GL type enum, e.g. `gl::FLOAT_VEC3`
+    pub gl_type: gl::types::GLenum,
+    /// number of array elements, 1 for non-arrays
+    pub size: gl::types::GLint,
+    /// -1 for variables without a location (e.g. uniforms in blocks)
+    pub location: gl::types::GLint,
+}
+
+impl ActiveVariable {
+    pub fn type_name(&self) -> &'static str {
+        type_name(self.gl_type)
+    }
+}
+
+impl fmt::Display for ActiveVariable {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} {}", self.type_name(), self.name)?;
+        if self.size > 1 {
+            write!(f, "[{}]", self.size)?;
+        }
+        if self.location >= 0 {
+            write!(f, " (location = {})", self.location)?;
+        }
+        Ok(())
+    }
+}
+
+// -=-=-=-=-=-=-=-=-=-=-=-=-=-=-=-=-=-=-=-=-=-=-=-=-=-=-=-
+
+/// What a linked program expects as inputs: its active vertex attributes and
+/// uniforms as reported by the driver. Attributes are sorted by location,
+/// uniforms by name.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ProgramInterface {
+    pub attributes: Vec<ActiveVariable>,
+    pub uniforms: Vec<ActiveVariable>,
+}
+
+impl ProgramInterface {
+    /// Queries `GL_ACTIVE_ATTRIBUTES` and `GL_ACTIVE_UNIFORMS` of the linked
+    /// program `id`.
+    pub fn query(gl: &gl::Gl, id: gl::types::GLuint) -> Self {
+        let mut attributes = query_active(
+            gl,
+            id,
+            gl::ACTIVE_ATTRIBUTES,
+            gl::ACTIVE_ATTRIBUTE_MAX_LENGTH,
+            |index, buf_size, length, size, gl_type, name| unsafe {
+                gl.GetActiveAttrib(id, index, buf_size, length, size, gl_type, name)
+            },
+            |name| unsafe { gl.GetAttribLocation(id, name) },
+        );
+        attributes.sort_by_key(|a| a.location);
+
+        let mut uniforms = query_active(
+            gl,
+            id,
+            gl::ACTIVE_UNIFORMS,
+            gl::ACTIVE_UNIFORM_MAX_LENGTH,
+            |index, buf_size, length, size, gl_type, name| unsafe {
+                gl.GetActiveUniform(id, index, buf_size, length, size, gl_type, name)
+            },
+            |name| unsafe { gl.GetUniformLocation(id, name) },
+        );
+        uniforms.sort_by(|a, b| a.name.cmp(&b.name));
+
+        ProgramInterface {
+            attributes,
+            uniforms,
+        }
+    }
+
+    pub fn attribute(&self, name: &str) -> Option<&ActiveVariable> {
+        self.attributes.iter().find(|a| a.name == name)
+    }
+
+    pub fn attribute_at(&self, location: gl::types::GLint) -> Option<&ActiveVariable> {
+        self.attributes.iter().find(|a| a.location == location)
+    }
+
+    pub fn uniform(&self, name: &str) -> Option<&ActiveVariable> {
+        self.uniforms.iter().find(|u| u.name == name)
+    }
+}
+
+impl fmt::Display for ProgramInterface {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "attributes:")?;
+        for a in &self.attributes {
+            writeln!(f, "  {}", a)?;
+        }
+        writeln!(f, "uniforms:")?;
+        for u in &self.uniforms {
+            writeln!(f, "  {}", u)?;
+        }
+        Ok(())
+    }
+}
+
+fn query_active<G, L>(
+    gl: &gl::Gl,
+    id: gl::types::GLuint,
+    count_param: gl::types::GLenum,
+    max_length_param: gl::types::GLenum,
+    get_active: G,
+    get_location: L,
+) -> Vec<ActiveVariable>
+where
+    G: Fn(
+        gl::types::GLuint,
+        gl::types::GLsizei,
+        *mut gl::types::GLsizei,
+        *mut gl::types::GLint,
+        *mut gl::types::GLenum,
+        *mut gl::types::GLchar,
+    ),
+    L: Fn(*const gl::types::GLchar) -> gl::types::GLint,
+{
+    let mut count: gl::types::GLint = 0;
+    let mut max_length: gl::types::GLint = 0;
+    unsafe {
+        gl.GetProgramiv(id, count_param, &mut count);
+        gl.GetProgramiv(id, max_length_param, &mut max_length);
+    }
+
+    (0..count as gl::types::GLuint)
+        .map(|index| {
+            // max_length includes the terminating 0
+            let mut name: Vec<u8> = vec![0; max_length.max(1) as usize];
+            let mut length: gl::types::GLsizei = 0;
+            let mut size: gl::types::GLint = 0;
+            let mut gl_type: gl::types::GLenum = 0;
+            get_active(
+                index,
+                name.len() as gl::types::GLsizei,
+                &mut length,
+                &mut size,
+                &mut gl_type,
+                name.as_mut_ptr() as *mut gl::types::GLchar,
+            );
+            let location = get_location(name.as_ptr() as *const gl::types::GLchar);
+            name.truncate(length as usize);
+            ActiveVariable {
+                name: String::from_utf8_lossy(&name).into_owned(),
+                gl_type,
+                size,
+                location,
+            }
+        })
+        .collect()
+}
+
+// -=-=-=-=-=-=-=-=-=-=-=-=-=-=-=-=-=-=-=-=-=-=-=-=-=-=-=-
+
+/// GLSL name of a GL type enum as returned by `glGetActiveAttrib` /
+/// `glGetActiveUniform`.
+pub fn type_name(gl_type: gl::types::GLenum) -> &'static str {
+    match gl_type {
+        gl::FLOAT => "float",
+        gl::FLOAT_VEC2 => "vec2",
+        gl::FLOAT_VEC3 => "vec3",
+        gl::FLOAT_VEC4 => "vec4",
+        gl::DOUBLE => "double",
+        gl::DOUBLE_VEC2 => "dvec2",
+        gl::DOUBLE_VEC3 => "dvec3",
+        gl::DOUBLE_VEC4 => "dvec4",
+        gl::INT => "int",
+        gl::INT_VEC2 => "ivec2",
+        gl::INT_VEC3 => "ivec3",
+        gl::INT_VEC4 => "ivec4",
+        gl::UNSIGNED_INT => "uint",
+        gl::UNSIGNED_INT_VEC2 => "uvec2",
+        gl::UNSIGNED_INT_VEC3 => "uvec3",
+        gl::UNSIGNED_INT_VEC4 => "uvec4",
+        gl::BOOL => "bool",
+        gl::BOOL_VEC2 => "bvec2",
+        gl::BOOL_VEC3 => "bvec3",
+        gl::BOOL_VEC4 => "bvec4",
+        gl::FLOAT_MAT2 => "mat2",
+        gl::FLOAT_MAT3 => "mat3",
+        gl::FLOAT_MAT4 => "mat4",
+        gl::FLOAT_MAT2x3 => "mat2x3",
+        gl::FLOAT_MAT2x4 => "mat2x4",
+        gl::FLOAT_MAT3x2 => "mat3x2",
+        gl::FLOAT_MAT3x4 => "mat3x4",
+        gl::FLOAT_MAT4x2 => "mat4x2",
+        gl::FLOAT_MAT4x3 => "mat4x3",
+        gl::DOUBLE_MAT2 => "dmat2",
+        gl::DOUBLE_MAT3 => "dmat3",
+        gl::DOUBLE_MAT4 => "dmat4",
+        gl::SAMPLER_1D => "sampler1D",
+        gl::SAMPLER_2D => "sampler2D",
+        gl::SAMPLER_3D => "sampler3D",
+        gl::SAMPLER_CUBE => "samplerCube",
+        gl::SAMPLER_2D_SHADOW => "sampler2DShadow",
+        gl::SAMPLER_2D_ARRAY => "sampler2DArray",
+        gl::SAMPLER_2D_ARRAY_SHADOW => "sampler2DArrayShadow",
+        gl::SAMPLER_CUBE_SHADOW => "samplerCubeShadow",
+        gl::SAMPLER_2D_MULTISAMPLE => "sampler2DMS",
+        gl::SAMPLER_BUFFER => "samplerBuffer",
+        gl::INT_SAMPLER_2D => "isampler2D",
+        gl::UNSIGNED_INT_SAMPLER_2D => "usampler2D",
+        gl::IMAGE_2D => "image2D",
+        _ => "<unknown>",
+    }
+}
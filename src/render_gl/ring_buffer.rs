@@ -0,0 +1,170 @@
+use super::buffer::{Buffer, BufferType};
+use super::capabilities;
+use failure::Fail;
+use gl;
+use std::mem::MaybeUninit;
+
+#[derive(Debug, Fail)]
+pub enum Error {
+    #[fail(display = "Persistent mapped buffers need GL 4.4 or ARB_buffer_storage")]
+    BufferStorageUnsupported,
+    #[fail(display = "Failed to map buffer of {} bytes", size)]
+    MapFailed { size: usize },
+}
+
+/// Flags for storage that stays mapped while the GPU reads from it.
+const PERSISTENT_FLAGS: gl::types::GLbitfield =
+    gl::MAP_WRITE_BIT | gl::MAP_PERSISTENT_BIT | gl::MAP_COHERENT_BIT;
+
+/// Number of sections: the CPU writes one while the GPU may still read the
+/// two previous frames.
+pub const DEFAULT_SECTIONS: usize = 3;
+
+/// Persistently mapped buffer split into sections that are written in turn,
+/// one per frame.
+///
+/// `next_section` waits for the fence of the section it hands out, so data is
+/// never overwritten while a previous draw call still reads it, and
+/// `finish_section` has to be called after the draw calls that use it.
+/// Writes go directly to driver memory, there is no copy as with `BufferData`.
+///
+/// ```ignore
+/// let sprites = ring.next_section();
+/// for (slot, sprite) in sprites.iter_mut().zip(&frame_sprites) {
+///     slot.write(*sprite);
+/// }
+/// ring.buffer().bind();
+/// // draw n elements starting at ring.section_offset()
+/// ring.finish_section();
+/// ```
+pub struct RingBuffer<B, T>
+where
+    B: BufferType,
+    T: Copy + 'static,
+{
+    gl: gl::Gl,
+    buffer: Buffer<B>,
+    ptr: *mut T,
+    section_len: usize,
+    fences: Vec<Option<gl::types::GLsync>>,
+    current: usize,
+}
+
+impl<B, T> RingBuffer<B, T>
+where
+    B: BufferType,
+    T: Copy + 'static,
+{
+    /// Triple buffered ring of `section_len` elements per section.
+    pub fn new(gl: &gl::Gl, section_len: usize) -> Result<Self, Error> {
+        Self::with_sections(gl, section_len, DEFAULT_SECTIONS)
+    }
+
+    pub fn with_sections(gl: &gl::Gl, section_len: usize, sections: usize) -> Result<Self, Error> {
+        assert!(section_len > 0 && sections > 0, "empty ring buffer");
+        if !Self::is_supported(gl) {
+            return Err(Error::BufferStorageUnsupported);
+        }
+
+        let buffer = Buffer::<B>::new(gl);
+        buffer.storage::<T>(section_len * sections, PERSISTENT_FLAGS);
+        let ptr = buffer.map::<T>(PERSISTENT_FLAGS);
+        if ptr.is_null() {
+            return Err(Error::MapFailed {
+                size: section_len * sections * std::mem::size_of::<T>(),
+            });
+        }
+
+        Ok(RingBuffer {
+            gl: gl.clone(),
+            buffer,
+            ptr,
+            section_len,
+            fences: vec![None; sections],
+            // so that the first next_section returns section 0
+            current: sections - 1,
+        })
+    }
+
+    pub fn is_supported(gl: &gl::Gl) -> bool {
+        capabilities::gl_version(gl) >= (4, 4)
+            || capabilities::has_extension(gl, "GL_ARB_buffer_storage")
+    }
+
+    /// The underlying buffer, to bind it or set up vertex attributes.
+    pub fn buffer(&self) -> &Buffer<B> {
+        &self.buffer
+    }
+
+    pub fn section_len(&self) -> usize {
+        self.section_len
+    }
+
+    /// Index of the first element of the current section in the buffer, e.g.
+    /// the `first` argument of `glDrawArrays`.
+    pub fn section_offset(&self) -> usize {
+        self.current * self.section_len
+    }
+
+    /// Byte offset of the current section in the buffer.
+    pub fn section_offset_bytes(&self) -> usize {
+        self.section_offset() * std::mem::size_of::<T>()
+    }
+
+    /// Advances to the next section and returns it for writing, blocking
+    /// until the GPU has finished the commands that read it last time. The
+    /// mapping is write only and starts out uninitialized, hence
+    /// `MaybeUninit`.
+    pub fn next_section(&mut self) -> &mut [MaybeUninit<T>] {
+        self.current = (self.current + 1) % self.fences.len();
+        if let Some(fence) = self.fences[self.current].take() {
+            self.wait(fence);
+        }
+        unsafe {
+            std::slice::from_raw_parts_mut(
+                self.ptr.add(self.section_offset()) as *mut MaybeUninit<T>,
+                self.section_len,
+            )
+        }
+    }
+
+    /// Marks the current section as in use by all commands issued so far.
+    pub fn finish_section(&mut self) {
+        let fence = unsafe { self.gl.FenceSync(gl::SYNC_GPU_COMMANDS_COMPLETE, 0) };
+        if let Some(old) = self.fences[self.current].replace(fence) {
+            unsafe {
+                self.gl.DeleteSync(old);
+            }
+        }
+    }
+
+    fn wait(&self, fence: gl::types::GLsync) {
+        // one second per try, the first try flushes so the fence can signal
+        let mut flags = gl::SYNC_FLUSH_COMMANDS_BIT;
+        loop {
+            let result = unsafe { self.gl.ClientWaitSync(fence, flags, 1_000_000_000) };
+            if result != gl::TIMEOUT_EXPIRED {
+                break;
+            }
+            flags = 0;
+        }
+        unsafe {
+            self.gl.DeleteSync(fence);
+        }
+    }
+}
+
+impl<B, T> Drop for RingBuffer<B, T>
+where
+    B: BufferType,
+    T: Copy + 'static,
+{
+    fn drop(&mut self) {
+        for fence in self.fences.iter_mut().filter_map(Option::take) {
+            unsafe {
+                self.gl.DeleteSync(fence);
+            }
+        }
+        self.buffer.unmap();
+    }
+}
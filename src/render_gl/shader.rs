@@ -1,9 +1,18 @@
+use super::diagnostics::Diagnostics;
+use super::preprocessor::{self, PreprocessedSource};
+use super::program_binary_cache::ProgramBinaryCache;
+use super::program_interface::ProgramInterface;
+use super::uniform::Uniform;
+use super::vertex::{self, LayoutMismatches, VertexAttrib};
 use crate::resources::{self, Resources};
 use failure::Fail;
 use gl;
 use std;
+use std::cell::RefCell;
+use std::collections::HashMap;
 use std::ffi::{CStr, CString};
-use std::path::{Path,PathBuf};
+use std::ops::Deref;
+use std::path::PathBuf;
 
 // -=-=-=-=-=-=-=-=-=-=-=-=-=-=-=-=-=-=-=-=-=-=-=-=-=-=-=-
 // helper
@@ -19,8 +28,14 @@ fn create_cstring_with_len(len: usize) -> CString {
 pub enum Error {
     #[fail(display = "Failed to link program {}", message)]
     LinkError { message: String },
-    #[fail(display = "Failed to compile program {}", message)]
-    CompileError { message: String },
+    #[fail(display = "Failed to compile shader {}\n{}", name, diagnostics)]
+    CompileError {
+        name: String,
+        path: PathBuf,
+        /// the raw info log
+        message: String,
+        diagnostics: Diagnostics,
+    },
     #[fail(display = "Cannot find shader type for {}", name)]
     CannotDetermineShaderTypeForResource { name: String },
     #[fail(display = "Cannot load resource for {}", name)]
@@ -28,6 +43,24 @@ pub enum Error {
         name: String,
         #[cause] inner: resources::Error,
     },
+    #[fail(display = "Program has no active uniform named {}", name)]
+    UnknownUniform { name: String },
+    #[fail(display = "Program has no active block named {}", name)]
+    UnknownBlock { name: String },
+    #[fail(display = "Uniform name {} contains 0", name)]
+    InvalidUniformName { name: String },
+    #[fail(display = "Shader source {} is not valid UTF-8", name)]
+    SourceNotUtf8 { name: String },
+    #[fail(display = "Include cycle: {}", chain)]
+    IncludeCycle { chain: String },
+    #[fail(display = "Failed to preprocess {}:{}: {}", name, line, message)]
+    Preprocess {
+        name: String,
+        line: usize,
+        message: String,
+    },
+    #[fail(display = "Vertex layout does not match program inputs:\n{}", mismatches)]
+    VertexLayoutMismatch { mismatches: LayoutMismatches },
 }
 
 // -=-=-=-=-=-=-=-=-=-=-=-=-=-=-=-=-=-=-=-=-=-=-=-=-=-=-=-
@@ -36,19 +69,103 @@ pub struct Program {
     gl: gl::Gl,
     id: gl::types::GLuint,
     pub paths: Vec<PathBuf>,
+    interface: ProgramInterface,
+    uniform_locations: RefCell<HashMap<String, gl::types::GLint>>,
 }
 
 impl Program {
+    /// Links `<name>.vert` and `<name>.frag` plus whichever of `<name>.geom`,
+    /// `<name>.tesc` and `<name>.tese` exist.
     pub fn from_res(gl: &gl::Gl, res: &Resources, name: &str) -> Result<Program, Error> {
-        const POSSIBLE_EXT: [&str; 2] = [".vert", ".frag"];
-        let shaders = POSSIBLE_EXT
+        Self::from_res_with_defines(gl, res, name, &[])
+    }
+
+    /// Like [`from_res`](#method.from_res) but injects `#define`s after the
+    /// `#version` line of every stage. Each define is either `NAME` or
+    /// `NAME=VALUE`.
+    pub fn from_res_with_defines(
+        gl: &gl::Gl,
+        res: &Resources,
+        name: &str,
+        defines: &[&str],
+    ) -> Result<Program, Error> {
+        let sources = Self::load_sources(res, name, defines)?;
+        Self::from_sources(gl, &sources, false)
+    }
+
+    /// Like [`from_res_with_defines`](#method.from_res_with_defines) but first
+    /// tries to load the linked program from `cache`. If there is no binary
+    /// for the current sources and driver, or the driver rejects it, the
+    /// program is compiled and the result is stored in `cache`.
+    pub fn from_res_with_binary_cache(
+        gl: &gl::Gl,
+        res: &Resources,
+        name: &str,
+        defines: &[&str],
+        cache: &ProgramBinaryCache,
+    ) -> Result<Program, Error> {
+        let sources = Self::load_sources(res, name, defines)?;
+        if !ProgramBinaryCache::is_supported(gl) {
+            return Self::from_sources(gl, &sources, false);
+        }
+
+        let key = ProgramBinaryCache::key(gl, &sources);
+        let paths = collect_paths(
+            sources
+                .iter()
+                .flat_map(|s| s.source.files.iter().map(|f| &f.path)),
+        );
+        if let Some((format, binary)) = cache.load(&key) {
+            if let Some(program) = Self::from_binary(gl, format, &binary, paths) {
+                return Ok(program);
+            }
+        }
+
+        let program = Self::from_sources(gl, &sources, true)?;
+        if let Some((format, binary)) = program.binary() {
+            cache.store(&key, format, &binary).unwrap_or_else(|err| {
+                println!("Failed to store program binary for {}. {:?}", name, err);
+            });
+        }
+        Ok(program)
+    }
+
+    fn load_sources(
+        res: &Resources,
+        name: &str,
+        defines: &[&str],
+    ) -> Result<Vec<ShaderSource>, Error> {
+        const REQUIRED_EXT: [&str; 2] = [".vert", ".frag"];
+        const OPTIONAL_EXT: [&str; 3] = [".geom", ".tesc", ".tese"];
+        let optional = OPTIONAL_EXT
             .iter()
-            .map(|file_ext| Shader::from_res(gl, res, &format!("{}{}", name, file_ext)))
+            .filter(|file_ext| res.exists(&format!("{}{}", name, file_ext)));
+        REQUIRED_EXT
+            .iter()
+            .chain(optional)
+            .map(|file_ext| ShaderSource::from_res(res, &format!("{}{}", name, file_ext), defines))
+            .collect()
+    }
+
+    fn from_sources(
+        gl: &gl::Gl,
+        sources: &[ShaderSource],
+        binary_retrievable: bool,
+    ) -> Result<Program, Error> {
+        let shaders = sources
+            .iter()
+            .map(|source| Shader::from_source(gl, source))
             .collect::<Result<Vec<Shader>, Error>>()?;
-        Self::from_shaders(gl, &shaders)
+        Self::from_shaders(gl, &shaders, binary_retrievable)
     }
 
-    fn from_shaders(gl: &gl::Gl, shaders: &[Shader]) -> Result<Program, Error> {
+    /// `binary_retrievable` needs to be set for [`binary`](#method.binary) to
+    /// work.
+    fn from_shaders(
+        gl: &gl::Gl,
+        shaders: &[Shader],
+        binary_retrievable: bool,
+    ) -> Result<Program, Error> {
         let id = unsafe { gl.CreateProgram() };
         for s in shaders {
             unsafe {
@@ -56,6 +173,13 @@ impl Program {
             }
         }
         unsafe {
+            if binary_retrievable {
+                gl.ProgramParameteri(
+                    id,
+                    gl::PROGRAM_BINARY_RETRIEVABLE_HINT,
+                    gl::TRUE as gl::types::GLint,
+                );
+            }
             gl.LinkProgram(id);
         }
 
@@ -76,6 +200,7 @@ impl Program {
                     std::ptr::null_mut(),
                     error.as_ptr() as *mut gl::types::GLchar,
                 );
+                gl.DeleteProgram(id);
             }
             return Err(Error::LinkError {
                 message: error.to_string_lossy().into_owned(),
@@ -88,65 +213,283 @@ impl Program {
             }
         }
 
-        let paths = shaders.into_iter().map(|s|s.path.to_path_buf()).collect();
+        // shaders of different stages may include the same files
+        let paths = collect_paths(shaders.iter().flat_map(|s| s.paths.iter()));
+        Ok(Self::from_linked(gl, id, paths))
+    }
+
+    /// Returns `None` if the driver does not accept `binary`.
+    fn from_binary(
+        gl: &gl::Gl,
+        format: gl::types::GLenum,
+        binary: &[u8],
+        paths: Vec<PathBuf>,
+    ) -> Option<Program> {
+        let id = unsafe { gl.CreateProgram() };
+        let mut success: gl::types::GLint = 0;
+        unsafe {
+            gl.ProgramBinary(
+                id,
+                format,
+                binary.as_ptr() as *const gl::types::GLvoid,
+                binary.len() as gl::types::GLsizei,
+            );
+            gl.GetProgramiv(id, gl::LINK_STATUS, &mut success);
+        }
+        if success == 0 {
+            unsafe {
+                gl.DeleteProgram(id);
+            }
+            return None;
+        }
+        Some(Self::from_linked(gl, id, paths))
+    }
+
+    fn from_linked(gl: &gl::Gl, id: gl::types::GLuint, paths: Vec<PathBuf>) -> Program {
+        let interface = ProgramInterface::query(gl, id);
+        Program {
+            gl: gl.clone(),
+            id,
+            paths,
+            interface,
+            uniform_locations: RefCell::new(HashMap::new()),
+        }
+    }
 
-        Ok(Program { gl: gl.clone(), id, paths })
+    /// The driver specific binary format and binary of the linked program.
+    /// Only available for programs linked with the retrievable hint, see
+    /// [`from_res_with_binary_cache`](#method.from_res_with_binary_cache).
+    pub fn binary(&self) -> Option<(gl::types::GLenum, Vec<u8>)> {
+        let mut len: gl::types::GLint = 0;
+        unsafe {
+            self.gl
+                .GetProgramiv(self.id, gl::PROGRAM_BINARY_LENGTH, &mut len);
+        }
+        if len <= 0 {
+            return None;
+        }
+        let mut binary: Vec<u8> = vec![0; len as usize];
+        let mut written: gl::types::GLsizei = 0;
+        let mut format: gl::types::GLenum = 0;
+        unsafe {
+            self.gl.GetProgramBinary(
+                self.id,
+                len,
+                &mut written,
+                &mut format,
+                binary.as_mut_ptr() as *mut gl::types::GLvoid,
+            );
+        }
+        binary.truncate(written as usize);
+        Some((format, binary))
     }
 
     pub fn id(&self) -> gl::types::GLuint {
         self.id
     }
 
+    /// Active attributes and uniforms, queried once after linking.
+    pub fn interface(&self) -> &ProgramInterface {
+        &self.interface
+    }
+
+    /// Checks that every attribute of `layout` (see
+    /// `#[derive(VertexAttribPointers)]`) has an active input at its location
+    /// with the same component count and base type.
+    pub fn check_vertex_layout(&self, layout: &[VertexAttrib]) -> Result<(), Error> {
+        let mismatches = vertex::check_layout(&self.interface, layout);
+        if !mismatches.is_empty() {
+            return Err(Error::VertexLayoutMismatch {
+                mismatches: LayoutMismatches(mismatches),
+            });
+        }
+        Ok(())
+    }
+
+    /// Makes this the current program, unless it already is.
     pub fn set_used(&self) {
+        self.gl.use_program(self.id);
+    }
+
+    /// Looks up the location of the uniform `name`. Locations are cached per
+    /// program, including misses, so this only hits the driver once per name.
+    pub fn uniform_location(&self, name: &str) -> Result<gl::types::GLint, Error> {
+        let cached = self.uniform_locations.borrow().get(name).cloned();
+        let location = match cached {
+            Some(location) => location,
+            None => {
+                let c_name = CString::new(name)
+                    .map_err(|_| Error::InvalidUniformName { name: name.into() })?;
+                let location = unsafe { self.gl.GetUniformLocation(self.id, c_name.as_ptr()) };
+                self.uniform_locations
+                    .borrow_mut()
+                    .insert(name.into(), location);
+                location
+            }
+        };
+
+        if location < 0 {
+            return Err(Error::UnknownUniform { name: name.into() });
+        }
+        Ok(location)
+    }
+
+    /// Makes the uniform block `name` read from the buffer bound to
+    /// `binding` with `UniformBuffer::bind_base`, so that several programs can
+    /// share one buffer.
+    pub fn bind_uniform_block(&self, name: &str, binding: u32) -> Result<(), Error> {
+        let c_name =
+            CString::new(name).map_err(|_| Error::InvalidUniformName { name: name.into() })?;
+        let index = unsafe { self.gl.GetUniformBlockIndex(self.id, c_name.as_ptr()) };
+        if index == gl::INVALID_INDEX {
+            return Err(Error::UnknownBlock { name: name.into() });
+        }
         unsafe {
-            self.gl.UseProgram(self.id);
+            self.gl.UniformBlockBinding(self.id, index, binding);
+        }
+        Ok(())
+    }
+
+    /// Like `bind_uniform_block` for a `buffer` block. Needs GL 4.3.
+    pub fn bind_storage_block(&self, name: &str, binding: u32) -> Result<(), Error> {
+        let c_name =
+            CString::new(name).map_err(|_| Error::InvalidUniformName { name: name.into() })?;
+        let index = unsafe {
+            self.gl
+                .GetProgramResourceIndex(self.id, gl::SHADER_STORAGE_BLOCK, c_name.as_ptr())
+        };
+        if index == gl::INVALID_INDEX {
+            return Err(Error::UnknownBlock { name: name.into() });
         }
+        unsafe {
+            self.gl.ShaderStorageBlockBinding(self.id, index, binding);
+        }
+        Ok(())
+    }
+
+    /// Writes `value` to the uniform `name`. The program has to be in use, see
+    /// [`set_used`](#method.set_used).
+    pub fn set_uniform<U: Uniform>(&self, name: &str, value: U) -> Result<(), Error> {
+        let location = self.uniform_location(name)?;
+        value.set_uniform(&self.gl, location);
+        Ok(())
     }
 }
 
 impl Drop for Program {
     fn drop(&mut self) {
+        self.gl.delete_program(self.id);
+    }
+}
+
+// -=-=-=-=-=-=-=-=-=-=-=-=-=-=-=-=-=-=-=-=-=-=-=-=-=-=-=-
+
+/// A program consisting of a single compute shader. Needs a GL 4.3 context.
+pub struct ComputeProgram {
+    program: Program,
+}
+
+impl ComputeProgram {
+    /// Links `<name>.comp`.
+    pub fn from_res(gl: &gl::Gl, res: &Resources, name: &str) -> Result<ComputeProgram, Error> {
+        let source = ShaderSource::from_res(res, &format!("{}.comp", name), &[])?;
+        Ok(ComputeProgram {
+            program: Program::from_sources(gl, &[source], false)?,
+        })
+    }
+
+    /// The `local_size_x/y/z` declared in the shader.
+    pub fn work_group_size(&self) -> [gl::types::GLint; 3] {
+        let mut size: [gl::types::GLint; 3] = [0; 3];
+        unsafe {
+            self.program.gl.GetProgramiv(
+                self.program.id,
+                gl::COMPUTE_WORK_GROUP_SIZE,
+                size.as_mut_ptr(),
+            );
+        }
+        size
+    }
+
+    /// Uses the program and launches `x * y * z` work groups.
+    pub fn dispatch(&self, x: u32, y: u32, z: u32) {
+        self.program.set_used();
+        unsafe {
+            self.program.gl.DispatchCompute(x, y, z);
+        }
+    }
+
+    /// Makes writes of previous dispatches visible to the operations in
+    /// `barriers`, e.g. `gl::VERTEX_ATTRIB_ARRAY_BARRIER_BIT` before drawing
+    /// from a buffer the shader wrote to.
+    pub fn memory_barrier(&self, barriers: gl::types::GLbitfield) {
         unsafe {
-            self.gl.DeleteProgram(self.id);
+            self.program.gl.MemoryBarrier(barriers);
         }
     }
 }
 
+impl Deref for ComputeProgram {
+    type Target = Program;
+
+    fn deref(&self) -> &Program {
+        &self.program
+    }
+}
+
 // -=-=-=-=-=-=-=-=-=-=-=-=-=-=-=-=-=-=-=-=-=-=-=-=-=-=-=-
 
-pub struct Shader {
-    gl: gl::Gl,
-    id: gl::types::GLuint,
-    path: PathBuf,
+/// A preprocessed shader stage that has not been compiled yet.
+pub struct ShaderSource {
+    pub kind: gl::types::GLenum,
+    pub source: PreprocessedSource,
 }
 
-impl Shader {
-    fn from_res(gl: &gl::Gl, res: &Resources, name: &str) -> Result<Shader, Error> {
-        const POSSIBLE_EXT: [(&str, gl::types::GLenum); 2] =
-            [(".vert", gl::VERTEX_SHADER), (".frag", gl::FRAGMENT_SHADER)];
-        let shader_kind = POSSIBLE_EXT
+impl ShaderSource {
+    /// Determines the stage from the file extension of `name`.
+    pub fn from_res(res: &Resources, name: &str, defines: &[&str]) -> Result<ShaderSource, Error> {
+        const POSSIBLE_EXT: [(&str, gl::types::GLenum); 6] = [
+            (".vert", gl::VERTEX_SHADER),
+            (".frag", gl::FRAGMENT_SHADER),
+            (".geom", gl::GEOMETRY_SHADER),
+            (".tesc", gl::TESS_CONTROL_SHADER),
+            (".tese", gl::TESS_EVALUATION_SHADER),
+            (".comp", gl::COMPUTE_SHADER),
+        ];
+        let kind = POSSIBLE_EXT
             .iter()
             .find(|&&(file_ext, _)| name.ends_with(file_ext))
             .map(|&(_, kind)| kind)
             .ok_or_else(|| Error::CannotDetermineShaderTypeForResource { name: name.into() })?;
-        let source = res.load_cstring(name).map_err(|e| Error::ResourceLoad {
-            name: name.into(),
-            inner: e,
-        })?;
-        Shader::from_source(gl, &source.content, &source.path.to_path_buf(), shader_kind)
+        let source = preprocessor::preprocess(res, name, defines)?;
+        Ok(ShaderSource { kind, source })
     }
+}
 
-    fn from_source(
-        gl: &gl::Gl,
-        source: &CStr,
-        path: &Path,
-        kind: gl::types::GLuint,
-    ) -> Result<Shader, Error> {
-        let id = shader_from_source(gl, source, kind)?;
+// -=-=-=-=-=-=-=-=-=-=-=-=-=-=-=-=-=-=-=-=-=-=-=-=-=-=-=-
+
+pub struct Shader {
+    gl: gl::Gl,
+    id: gl::types::GLuint,
+    paths: Vec<PathBuf>,
+}
+
+impl Shader {
+    fn from_source(gl: &gl::Gl, source: &ShaderSource) -> Result<Shader, Error> {
+        let ShaderSource { kind, source } = source;
+        let id = shader_from_source(gl, &source.content, *kind).map_err(|message| {
+            let root = &source.files[0];
+            Error::CompileError {
+                name: root.name.clone(),
+                path: root.path.clone(),
+                diagnostics: Diagnostics::from_log(&message, &source.files),
+                message,
+            }
+        })?;
         Ok(Shader {
             gl: gl.clone(),
             id,
-            path: path.to_path_buf(),
+            paths: source.paths(),
         })
     }
 
@@ -163,11 +506,25 @@ impl Drop for Shader {
     }
 }
 
+fn collect_paths<'a, I>(paths: I) -> Vec<PathBuf>
+where
+    I: Iterator<Item = &'a PathBuf>,
+{
+    let mut result: Vec<PathBuf> = Vec::new();
+    for path in paths {
+        if !result.contains(path) {
+            result.push(path.clone());
+        }
+    }
+    result
+}
+
+/// Returns the info log as error if compilation fails.
 fn shader_from_source(
     gl: &gl::Gl,
     source: &CStr,
     kind: gl::types::GLuint,
-) -> Result<gl::types::GLuint, Error> {
+) -> Result<gl::types::GLuint, String> {
     let id = unsafe { gl.CreateShader(kind) };
     unsafe {
         gl.ShaderSource(id, 1, &source.as_ptr(), std::ptr::null());
@@ -190,10 +547,9 @@ fn shader_from_source(
                 std::ptr::null_mut(),
                 error.as_ptr() as *mut gl::types::GLchar,
             );
+            gl.DeleteShader(id);
         }
-        return Err(Error::CompileError {
-            message: error.to_string_lossy().into_owned(),
-        });
+        return Err(error.to_string_lossy().into_owned());
     }
 
     Ok(id)
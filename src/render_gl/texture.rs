@@ -0,0 +1,594 @@
+use crate::resources::{Image, ImageColor};
+use gl;
+
+// EXT_texture_filter_anisotropic, core since GL 4.6
+const TEXTURE_MAX_ANISOTROPY: gl::types::GLenum = 0x84FE;
+const MAX_TEXTURE_MAX_ANISOTROPY: gl::types::GLenum = 0x84FF;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum TextureKind {
+    Texture2D,
+    Texture2DArray,
+    CubeMap,
+}
+
+impl TextureKind {
+    pub fn target(self) -> gl::types::GLenum {
+        match self {
+            TextureKind::Texture2D => gl::TEXTURE_2D,
+            TextureKind::Texture2DArray => gl::TEXTURE_2D_ARRAY,
+            TextureKind::CubeMap => gl::TEXTURE_CUBE_MAP,
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum CubeFace {
+    PositiveX,
+    NegativeX,
+    PositiveY,
+    NegativeY,
+    PositiveZ,
+    NegativeZ,
+}
+
+impl CubeFace {
+    pub const ALL: [CubeFace; 6] = [
+        CubeFace::PositiveX,
+        CubeFace::NegativeX,
+        CubeFace::PositiveY,
+        CubeFace::NegativeY,
+        CubeFace::PositiveZ,
+        CubeFace::NegativeZ,
+    ];
+
+    pub fn target(self) -> gl::types::GLenum {
+        match self {
+            CubeFace::PositiveX => gl::TEXTURE_CUBE_MAP_POSITIVE_X,
+            CubeFace::NegativeX => gl::TEXTURE_CUBE_MAP_NEGATIVE_X,
+            CubeFace::PositiveY => gl::TEXTURE_CUBE_MAP_POSITIVE_Y,
+            CubeFace::NegativeY => gl::TEXTURE_CUBE_MAP_NEGATIVE_Y,
+            CubeFace::PositiveZ => gl::TEXTURE_CUBE_MAP_POSITIVE_Z,
+            CubeFace::NegativeZ => gl::TEXTURE_CUBE_MAP_NEGATIVE_Z,
+        }
+    }
+}
+
+// -=-=-=-=-=-=-=-=-=-=-=-=-=-=-=-=-=-=-=-=-=-=-=-=-=-=-=-
+
+/// Storage format of a texture together with the layout of the pixel data
+/// that is uploaded to it.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum PixelFormat {
+    R8,
+    Rg8,
+    Rgb8,
+    Rgba8,
+    Srgb8,
+    Srgb8Alpha8,
+    R16F,
+    Rgba16F,
+    R32F,
+    Rgb32F,
+    Rgba32F,
+    Depth24,
+    Depth32F,
+    Depth24Stencil8,
+}
+
+impl PixelFormat {
+    pub fn internal_format(self) -> gl::types::GLenum {
+        match self {
+            PixelFormat::R8 => gl::R8,
+            PixelFormat::Rg8 => gl::RG8,
+            PixelFormat::Rgb8 => gl::RGB8,
+            PixelFormat::Rgba8 => gl::RGBA8,
+            PixelFormat::Srgb8 => gl::SRGB8,
+            PixelFormat::Srgb8Alpha8 => gl::SRGB8_ALPHA8,
+            PixelFormat::R16F => gl::R16F,
+            PixelFormat::Rgba16F => gl::RGBA16F,
+            PixelFormat::R32F => gl::R32F,
+            PixelFormat::Rgb32F => gl::RGB32F,
+            PixelFormat::Rgba32F => gl::RGBA32F,
+            PixelFormat::Depth24 => gl::DEPTH_COMPONENT24,
+            PixelFormat::Depth32F => gl::DEPTH_COMPONENT32F,
+            PixelFormat::Depth24Stencil8 => gl::DEPTH24_STENCIL8,
+        }
+    }
+
+    /// Format of the client side pixel data.
+    pub fn format(self) -> gl::types::GLenum {
+        match self {
+            PixelFormat::R8 | PixelFormat::R16F | PixelFormat::R32F => gl::RED,
+            PixelFormat::Rg8 => gl::RG,
+            PixelFormat::Rgb8 | PixelFormat::Srgb8 | PixelFormat::Rgb32F => gl::RGB,
+            PixelFormat::Rgba8
+            | PixelFormat::Srgb8Alpha8
+            | PixelFormat::Rgba16F
+            | PixelFormat::Rgba32F => gl::RGBA,
+            PixelFormat::Depth24 | PixelFormat::Depth32F => gl::DEPTH_COMPONENT,
+            PixelFormat::Depth24Stencil8 => gl::DEPTH_STENCIL,
+        }
+    }
+
+    /// Type of the client side pixel data. Half float formats are uploaded
+    /// from `f32`.
+    pub fn data_type(self) -> gl::types::GLenum {
+        match self {
+            PixelFormat::R8
+            | PixelFormat::Rg8
+            | PixelFormat::Rgb8
+            | PixelFormat::Rgba8
+            | PixelFormat::Srgb8
+            | PixelFormat::Srgb8Alpha8 => gl::UNSIGNED_BYTE,
+            PixelFormat::R16F
+            | PixelFormat::Rgba16F
+            | PixelFormat::R32F
+            | PixelFormat::Rgb32F
+            | PixelFormat::Rgba32F
+            | PixelFormat::Depth32F => gl::FLOAT,
+            PixelFormat::Depth24 => gl::UNSIGNED_INT,
+            PixelFormat::Depth24Stencil8 => gl::UNSIGNED_INT_24_8,
+        }
+    }
+
+    /// Bytes per pixel of the client side pixel data.
+    pub fn bytes_per_pixel(self) -> usize {
+        match self {
+            PixelFormat::R8 => 1,
+            PixelFormat::Rg8 => 2,
+            PixelFormat::Rgb8 | PixelFormat::Srgb8 => 3,
+            PixelFormat::Rgba8 | PixelFormat::Srgb8Alpha8 => 4,
+            PixelFormat::R16F | PixelFormat::R32F => 4,
+            PixelFormat::Rgba16F | PixelFormat::Rgba32F => 16,
+            PixelFormat::Rgb32F => 12,
+            PixelFormat::Depth24 | PixelFormat::Depth32F | PixelFormat::Depth24Stencil8 => 4,
+        }
+    }
+
+    /// The linear 8 bit format matching the channels of `color`. Gray images
+    /// end up in the red channel.
+    pub fn for_image(color: ImageColor) -> Self {
+        match color {
+            ImageColor::Gray => PixelFormat::R8,
+            ImageColor::GrayAlpha => PixelFormat::Rg8,
+            ImageColor::Rgb => PixelFormat::Rgb8,
+            ImageColor::Rgba => PixelFormat::Rgba8,
+        }
+    }
+}
+
+// -=-=-=-=-=-=-=-=-=-=-=-=-=-=-=-=-=-=-=-=-=-=-=-=-=-=-=-
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Filter {
+    Nearest,
+    Linear,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Wrap {
+    Repeat,
+    MirroredRepeat,
+    ClampToEdge,
+    ClampToBorder,
+}
+
+impl Filter {
+    pub(super) fn gl_enum(self) -> gl::types::GLenum {
+        match self {
+            Filter::Nearest => gl::NEAREST,
+            Filter::Linear => gl::LINEAR,
+        }
+    }
+}
+
+impl Wrap {
+    fn gl_enum(self) -> gl::types::GLenum {
+        match self {
+            Wrap::Repeat => gl::REPEAT,
+            Wrap::MirroredRepeat => gl::MIRRORED_REPEAT,
+            Wrap::ClampToEdge => gl::CLAMP_TO_EDGE,
+            Wrap::ClampToBorder => gl::CLAMP_TO_BORDER,
+        }
+    }
+}
+
+/// Sampling parameters of a texture.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct TextureParams {
+    pub min_filter: Filter,
+    pub mag_filter: Filter,
+    /// Filter between mipmap levels, `None` to only sample the base level.
+    pub mipmap_filter: Option<Filter>,
+    pub wrap_s: Wrap,
+    pub wrap_t: Wrap,
+    pub wrap_r: Wrap,
+    /// 1.0 disables anisotropic filtering. Clamped to what the driver
+    /// supports and ignored without `EXT_texture_filter_anisotropic`.
+    pub max_anisotropy: f32,
+}
+
+impl Default for TextureParams {
+    fn default() -> Self {
+        TextureParams {
+            min_filter: Filter::Linear,
+            mag_filter: Filter::Linear,
+            mipmap_filter: None,
+            wrap_s: Wrap::Repeat,
+            wrap_t: Wrap::Repeat,
+            wrap_r: Wrap::Repeat,
+            max_anisotropy: 1.0,
+        }
+    }
+}
+
+impl TextureParams {
+    /// Trilinear filtering, to be used with `Texture::generate_mipmaps`.
+    pub fn mipmapped() -> Self {
+        TextureParams {
+            mipmap_filter: Some(Filter::Linear),
+            ..Default::default()
+        }
+    }
+
+    fn min_filter_enum(&self) -> gl::types::GLenum {
+        match (self.min_filter, self.mipmap_filter) {
+            (Filter::Nearest, None) => gl::NEAREST,
+            (Filter::Linear, None) => gl::LINEAR,
+            (Filter::Nearest, Some(Filter::Nearest)) => gl::NEAREST_MIPMAP_NEAREST,
+            (Filter::Nearest, Some(Filter::Linear)) => gl::NEAREST_MIPMAP_LINEAR,
+            (Filter::Linear, Some(Filter::Nearest)) => gl::LINEAR_MIPMAP_NEAREST,
+            (Filter::Linear, Some(Filter::Linear)) => gl::LINEAR_MIPMAP_LINEAR,
+        }
+    }
+
+    fn mag_filter_enum(&self) -> gl::types::GLenum {
+        self.mag_filter.gl_enum()
+    }
+}
+
+// -=-=-=-=-=-=-=-=-=-=-=-=-=-=-=-=-=-=-=-=-=-=-=-=-=-=-=-
+
+pub struct Texture {
+    gl: gl::Gl,
+    id: gl::types::GLuint,
+    kind: TextureKind,
+    format: PixelFormat,
+    width: u32,
+    height: u32,
+    layers: u32,
+}
+
+impl Texture {
+    /// 2D texture with uninitialized content.
+    pub fn new_2d(gl: &gl::Gl, width: u32, height: u32, format: PixelFormat) -> Self {
+        let texture = Self::create(gl, TextureKind::Texture2D, format, width, height, 1);
+        texture.bind();
+        unsafe {
+            gl.TexImage2D(
+                gl::TEXTURE_2D,
+                0,
+                format.internal_format() as gl::types::GLint,
+                width as gl::types::GLsizei,
+                height as gl::types::GLsizei,
+                0,
+                format.format(),
+                format.data_type(),
+                std::ptr::null(),
+            );
+        }
+        texture.set_params(&TextureParams::default());
+        texture
+    }
+
+    /// 2D array texture with `layers` layers and uninitialized content.
+    pub fn new_2d_array(
+        gl: &gl::Gl,
+        width: u32,
+        height: u32,
+        layers: u32,
+        format: PixelFormat,
+    ) -> Self {
+        let texture = Self::create(
+            gl,
+            TextureKind::Texture2DArray,
+            format,
+            width,
+            height,
+            layers,
+        );
+        texture.bind();
+        unsafe {
+            gl.TexImage3D(
+                gl::TEXTURE_2D_ARRAY,
+                0,
+                format.internal_format() as gl::types::GLint,
+                width as gl::types::GLsizei,
+                height as gl::types::GLsizei,
+                layers as gl::types::GLsizei,
+                0,
+                format.format(),
+                format.data_type(),
+                std::ptr::null(),
+            );
+        }
+        texture.set_params(&TextureParams::default());
+        texture
+    }
+
+    /// Cube map with square faces of `size` and uninitialized content.
+    pub fn new_cube_map(gl: &gl::Gl, size: u32, format: PixelFormat) -> Self {
+        let texture = Self::create(gl, TextureKind::CubeMap, format, size, size, 6);
+        texture.bind();
+        for face in CubeFace::ALL.iter() {
+            unsafe {
+                gl.TexImage2D(
+                    face.target(),
+                    0,
+                    format.internal_format() as gl::types::GLint,
+                    size as gl::types::GLsizei,
+                    size as gl::types::GLsizei,
+                    0,
+                    format.format(),
+                    format.data_type(),
+                    std::ptr::null(),
+                );
+            }
+        }
+        texture.set_params(&TextureParams {
+            wrap_s: Wrap::ClampToEdge,
+            wrap_t: Wrap::ClampToEdge,
+            wrap_r: Wrap::ClampToEdge,
+            ..Default::default()
+        });
+        texture
+    }
+
+    /// 2D texture from an image loaded with `Resources::load_image`. Rows are
+    /// flipped so that texture coordinate (0, 0) is the bottom left corner of
+    /// the image.
+    pub fn from_image(gl: &gl::Gl, image: &Image) -> Self {
+        let format = PixelFormat::for_image(image.color);
+        let texture = Self::new_2d(gl, image.width, image.height, format);
+        let row_len = image.width as usize * image.color.channels();
+        let flipped: Vec<u8> = image
+            .data
+            .chunks(row_len.max(1))
+            .rev()
+            .flat_map(|row| row.iter().cloned())
+            .collect();
+        texture.upload(&flipped);
+        texture
+    }
+
+    fn create(
+        gl: &gl::Gl,
+        kind: TextureKind,
+        format: PixelFormat,
+        width: u32,
+        height: u32,
+        layers: u32,
+    ) -> Self {
+        let mut id: gl::types::GLuint = 0;
+        unsafe {
+            gl.GenTextures(1, &mut id);
+        }
+        Texture {
+            gl: gl.clone(),
+            id,
+            kind,
+            format,
+            width,
+            height,
+            layers,
+        }
+    }
+
+    pub fn id(&self) -> gl::types::GLuint {
+        self.id
+    }
+
+    pub fn kind(&self) -> TextureKind {
+        self.kind
+    }
+
+    pub fn format(&self) -> PixelFormat {
+        self.format
+    }
+
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+
+    pub fn height(&self) -> u32 {
+        self.height
+    }
+
+    pub fn layers(&self) -> u32 {
+        self.layers
+    }
+
+    pub fn bind(&self) {
+        unsafe {
+            self.gl.BindTexture(self.kind.target(), self.id);
+        }
+    }
+
+    pub fn unbind(&self) {
+        unsafe {
+            self.gl.BindTexture(self.kind.target(), 0);
+        }
+    }
+
+    /// Binds the texture to texture unit `unit`, pass the same number as
+    /// `Sampler` uniform. Unit 0 is made active again afterwards, so that
+    /// creating or updating other textures does not replace this binding.
+    pub fn bind_to_unit(&self, unit: u32) {
+        unsafe {
+            self.gl.ActiveTexture(gl::TEXTURE0 + unit);
+            self.gl.BindTexture(self.kind.target(), self.id);
+            self.gl.ActiveTexture(gl::TEXTURE0);
+        }
+    }
+
+    pub fn set_params(&self, params: &TextureParams) {
+        let target = self.kind.target();
+        self.bind();
+        unsafe {
+            let gl = &self.gl;
+            gl.TexParameteri(
+                target,
+                gl::TEXTURE_MIN_FILTER,
+                params.min_filter_enum() as gl::types::GLint,
+            );
+            gl.TexParameteri(
+                target,
+                gl::TEXTURE_MAG_FILTER,
+                params.mag_filter_enum() as gl::types::GLint,
+            );
+            gl.TexParameteri(
+                target,
+                gl::TEXTURE_WRAP_S,
+                params.wrap_s.gl_enum() as gl::types::GLint,
+            );
+            gl.TexParameteri(
+                target,
+                gl::TEXTURE_WRAP_T,
+                params.wrap_t.gl_enum() as gl::types::GLint,
+            );
+            gl.TexParameteri(
+                target,
+                gl::TEXTURE_WRAP_R,
+                params.wrap_r.gl_enum() as gl::types::GLint,
+            );
+        }
+
+        if params.max_anisotropy > 1.0 && self.gl.has_anisotropic_filtering() {
+            let mut max: gl::types::GLfloat = 1.0;
+            unsafe {
+                self.gl.GetFloatv(MAX_TEXTURE_MAX_ANISOTROPY, &mut max);
+                self.gl.TexParameterf(
+                    target,
+                    TEXTURE_MAX_ANISOTROPY,
+                    params.max_anisotropy.min(max),
+                );
+            }
+        }
+    }
+
+    pub fn generate_mipmaps(&self) {
+        self.bind();
+        unsafe {
+            self.gl.GenerateMipmap(self.kind.target());
+        }
+    }
+
+    /// Replaces the content of a 2D texture. `data` has to match the size and
+    /// `PixelFormat` the texture was created with, rows from bottom to top.
+    pub fn upload<T>(&self, data: &[T]) {
+        assert_eq!(
+            self.kind,
+            TextureKind::Texture2D,
+            "upload needs a 2D texture"
+        );
+        self.check_len(data);
+        self.bind();
+        self.with_tight_rows(|| unsafe {
+            self.gl.TexSubImage2D(
+                gl::TEXTURE_2D,
+                0,
+                0,
+                0,
+                self.width as gl::types::GLsizei,
+                self.height as gl::types::GLsizei,
+                self.format.format(),
+                self.format.data_type(),
+                data.as_ptr() as *const gl::types::GLvoid,
+            );
+        });
+    }
+
+    /// Replaces one layer of a 2D array texture.
+    pub fn upload_layer<T>(&self, layer: u32, data: &[T]) {
+        assert_eq!(
+            self.kind,
+            TextureKind::Texture2DArray,
+            "upload_layer needs a 2D array texture"
+        );
+        assert!(layer < self.layers, "layer {} out of range", layer);
+        self.check_len(data);
+        self.bind();
+        self.with_tight_rows(|| unsafe {
+            self.gl.TexSubImage3D(
+                gl::TEXTURE_2D_ARRAY,
+                0,
+                0,
+                0,
+                layer as gl::types::GLint,
+                self.width as gl::types::GLsizei,
+                self.height as gl::types::GLsizei,
+                1,
+                self.format.format(),
+                self.format.data_type(),
+                data.as_ptr() as *const gl::types::GLvoid,
+            );
+        });
+    }
+
+    /// Replaces one face of a cube map.
+    pub fn upload_face<T>(&self, face: CubeFace, data: &[T]) {
+        assert_eq!(
+            self.kind,
+            TextureKind::CubeMap,
+            "upload_face needs a cube map"
+        );
+        self.check_len(data);
+        self.bind();
+        self.with_tight_rows(|| unsafe {
+            self.gl.TexSubImage2D(
+                face.target(),
+                0,
+                0,
+                0,
+                self.width as gl::types::GLsizei,
+                self.height as gl::types::GLsizei,
+                self.format.format(),
+                self.format.data_type(),
+                data.as_ptr() as *const gl::types::GLvoid,
+            );
+        });
+    }
+
+    /// Runs `f` with `UNPACK_ALIGNMENT` set to 1, as pixel rows are tightly
+    /// packed, and restores the previous alignment afterwards.
+    fn with_tight_rows<F: FnOnce()>(&self, f: F) {
+        let mut alignment: gl::types::GLint = 0;
+        unsafe {
+            self.gl.GetIntegerv(gl::UNPACK_ALIGNMENT, &mut alignment);
+            self.gl.PixelStorei(gl::UNPACK_ALIGNMENT, 1);
+        }
+        f();
+        unsafe {
+            self.gl.PixelStorei(gl::UNPACK_ALIGNMENT, alignment);
+        }
+    }
+
+    fn check_len<T>(&self, data: &[T]) {
+        let expected = self.width as usize * self.height as usize * self.format.bytes_per_pixel();
+        let actual = std::mem::size_of_val(data);
+        assert_eq!(
+            expected, actual,
+            "pixel data for a {}x{} {:?} texture needs {} bytes",
+            self.width, self.height, self.format, expected
+        );
+    }
+}
+
+impl Drop for Texture {
+    fn drop(&mut self) {
+        unsafe {
+            self.gl.DeleteTextures(1, &self.id);
+        }
+    }
+}
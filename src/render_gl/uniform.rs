@@ -0,0 +1,86 @@
+use gl;
+use nalgebra as na;
+
+/// Values that can be written to a uniform location of the program that is
+/// currently in use.
+pub trait Uniform {
+    fn set_uniform(&self, gl: &gl::Gl, location: gl::types::GLint);
+}
+
+/// Texture unit index for `sampler*` uniforms.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Sampler(pub u32);
+
+impl Uniform for f32 {
+    fn set_uniform(&self, gl: &gl::Gl, location: gl::types::GLint) {
+        unsafe {
+            gl.Uniform1f(location, *self);
+        }
+    }
+}
+
+impl Uniform for i32 {
+    fn set_uniform(&self, gl: &gl::Gl, location: gl::types::GLint) {
+        unsafe {
+            gl.Uniform1i(location, *self);
+        }
+    }
+}
+
+impl Uniform for Sampler {
+    fn set_uniform(&self, gl: &gl::Gl, location: gl::types::GLint) {
+        unsafe {
+            gl.Uniform1i(location, self.0 as gl::types::GLint);
+        }
+    }
+}
+
+impl Uniform for na::Vector2<f32> {
+    fn set_uniform(&self, gl: &gl::Gl, location: gl::types::GLint) {
+        unsafe {
+            gl.Uniform2fv(location, 1, self.as_ptr());
+        }
+    }
+}
+
+impl Uniform for na::Vector3<f32> {
+    fn set_uniform(&self, gl: &gl::Gl, location: gl::types::GLint) {
+        unsafe {
+            gl.Uniform3fv(location, 1, self.as_ptr());
+        }
+    }
+}
+
+impl Uniform for na::Vector4<f32> {
+    fn set_uniform(&self, gl: &gl::Gl, location: gl::types::GLint) {
+        unsafe {
+            gl.Uniform4fv(location, 1, self.as_ptr());
+        }
+    }
+}
+
+// nalgebra stores matrices column-major, just like GL expects them
+impl Uniform for na::Matrix3<f32> {
+    fn set_uniform(&self, gl: &gl::Gl, location: gl::types::GLint) {
+        unsafe {
+            gl.UniformMatrix3fv(location, 1, gl::FALSE, self.as_ptr());
+        }
+    }
+}
+
+impl Uniform for na::Matrix4<f32> {
+    fn set_uniform(&self, gl: &gl::Gl, location: gl::types::GLint) {
+        unsafe {
+            gl.UniformMatrix4fv(location, 1, gl::FALSE, self.as_ptr());
+        }
+    }
+}
+
+impl<U> Uniform for &U
+where
+    U: Uniform + ?Sized,
+{
+    fn set_uniform(&self, gl: &gl::Gl, location: gl::types::GLint) {
+        (*self).set_uniform(gl, location);
+    }
+}
@@ -0,0 +1,650 @@
+use super::program_interface::ProgramInterface;
+use gl;
+use std::collections::HashMap;
+use std::fmt;
+
+/// How a vertex attribute is presented to the shader, i.e. which family of
+/// GLSL input types it can feed.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum AttribBaseType {
+    /// `float`, `vecN`, `matN` (via `glVertexAttribPointer`)
+    Float,
+    /// `int`, `ivecN` (via `glVertexAttribIPointer`)
+    Int,
+    /// `uint`, `uvecN` (via `glVertexAttribIPointer`)
+    UnsignedInt,
+    /// `double`, `dvecN` (via `glVertexAttribLPointer`)
+    Double,
+}
+
+impl fmt::Display for AttribBaseType {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            AttribBaseType::Float => "float",
+            AttribBaseType::Int => "int",
+            AttribBaseType::UnsignedInt => "uint",
+            AttribBaseType::Double => "double",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+/// The buffer-side format of a vertex attribute type from `render_gl::data`.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct AttribFormat {
+    pub components: usize,
+    /// GL type of the data in the buffer, e.g. `gl::FLOAT`
+    pub gl_type: gl::types::GLenum,
+    pub normalized: bool,
+    pub base_type: AttribBaseType,
+}
+
+impl AttribFormat {
+    /// Bytes GL reads for one attribute of this format.
+    pub const fn size(&self) -> usize {
+        match self.gl_type {
+            gl::BYTE | gl::UNSIGNED_BYTE => self.components,
+            gl::SHORT | gl::UNSIGNED_SHORT | gl::HALF_FLOAT => 2 * self.components,
+            gl::DOUBLE => 8 * self.components,
+            // all components in one 32 bit word
+            gl::INT_2_10_10_10_REV
+            | gl::UNSIGNED_INT_2_10_10_10_REV
+            | gl::UNSIGNED_INT_10F_11F_11F_REV => 4,
+            _ => 4 * self.components,
+        }
+    }
+
+    /// Enables the attribute at `location` and points it at the bound array
+    /// buffer, with the `glVertexAttrib*Pointer` variant of `base_type`.
+    ///
+    /// # Safety
+    ///
+    /// A vertex array and an array buffer must be bound, and `stride` and
+    /// `offset` must describe data of this format inside the buffer.
+    pub unsafe fn vertex_attrib_pointer(
+        &self,
+        gl: &gl::Gl,
+        location: usize,
+        stride: usize,
+        offset: usize,
+    ) {
+        let location = location as gl::types::GLuint;
+        let components = self.components as gl::types::GLint;
+        let stride = stride as gl::types::GLint;
+        let offset = offset as *const gl::types::GLvoid;
+        gl.EnableVertexAttribArray(location);
+        match self.base_type {
+            AttribBaseType::Float => {
+                let normalized = if self.normalized { gl::TRUE } else { gl::FALSE };
+                gl.VertexAttribPointer(
+                    location,
+                    components,
+                    self.gl_type,
+                    normalized,
+                    stride,
+                    offset,
+                );
+            }
+            AttribBaseType::Int | AttribBaseType::UnsignedInt => {
+                gl.VertexAttribIPointer(location, components, self.gl_type, stride, offset);
+            }
+            AttribBaseType::Double => {
+                gl.VertexAttribLPointer(location, components, self.gl_type, stride, offset);
+            }
+        }
+    }
+
+    /// Direct state access counterpart of `vertex_attrib_pointer`: sets the
+    /// format of attribute `location` of vertex array `vao`, at `offset`
+    /// from the start of a vertex. The buffer binding is set separately.
+    ///
+    /// # Safety
+    ///
+    /// `vao` must be a vertex array created with `glCreateVertexArrays`, and
+    /// the context must support GL 4.5 or ARB_direct_state_access.
+    pub unsafe fn vertex_array_attrib_format(
+        &self,
+        gl: &gl::Gl,
+        vao: gl::types::GLuint,
+        location: usize,
+        offset: usize,
+    ) {
+        let location = location as gl::types::GLuint;
+        let components = self.components as gl::types::GLint;
+        let offset = offset as gl::types::GLuint;
+        match self.base_type {
+            AttribBaseType::Float => {
+                let normalized = if self.normalized { gl::TRUE } else { gl::FALSE };
+                gl.VertexArrayAttribFormat(
+                    vao,
+                    location,
+                    components,
+                    self.gl_type,
+                    normalized,
+                    offset,
+                );
+            }
+            AttribBaseType::Int | AttribBaseType::UnsignedInt => {
+                gl.VertexArrayAttribIFormat(vao, location, components, self.gl_type, offset);
+            }
+            AttribBaseType::Double => {
+                gl.VertexArrayAttribLFormat(vao, location, components, self.gl_type, offset);
+            }
+        }
+    }
+}
+
+/// One entry of a vertex layout as generated by
+/// `#[derive(VertexAttribPointers)]`.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct VertexAttrib {
+    /// name of the struct field
+    pub name: &'static str,
+    pub location: usize,
+    /// byte offset inside the vertex
+    pub offset: usize,
+    pub format: AttribFormat,
+    /// 0 for per-vertex data, N to advance once every N instances
+    pub divisor: u32,
+}
+
+/// A field type of a vertex struct that feeds one attribute location,
+/// implemented by the types in `render_gl::data`.
+#[diagnostic::on_unimplemented(
+    message = "`{Self}` is not a vertex attribute type",
+    note = "use a type from `render_gl::data`, an array of them, a nalgebra matrix, \
+            or `#[flatten]` for a nested vertex struct"
+)]
+pub trait VertexAttribute: Copy {
+    const FORMAT: AttribFormat;
+
+    /// # Safety
+    ///
+    /// See `AttribFormat::vertex_attrib_pointer`.
+    unsafe fn vertex_attrib_pointer(gl: &gl::Gl, location: usize, stride: usize, offset: usize) {
+        Self::FORMAT.vertex_attrib_pointer(gl, location, stride, offset);
+    }
+}
+
+/// A vertex struct, implemented by `#[derive(VertexAttribPointers)]`, so
+/// that generic code like `Mesh` can set up and check its attributes.
+#[diagnostic::on_unimplemented(
+    message = "`{Self}` is not a vertex type",
+    note = "derive it with `#[derive(VertexAttribPointers)]`"
+)]
+pub trait Vertex: Sized + 'static {
+    /// Sets up the attribute pointers of all fields for the bound vertex
+    /// array and array buffer.
+    fn vertex_attrib_pointers(gl: &gl::Gl) {
+        let stride = std::mem::size_of::<Self>();
+        for attrib in Self::vertex_layout() {
+            unsafe {
+                attrib
+                    .format
+                    .vertex_attrib_pointer(gl, attrib.location, stride, attrib.offset);
+                if attrib.divisor != 0 {
+                    gl.VertexAttribDivisor(attrib.location as gl::types::GLuint, attrib.divisor);
+                }
+            }
+        }
+    }
+
+    /// One entry per location: arrays and matrices have one per element or
+    /// column, flattened structs contribute all of theirs.
+    fn vertex_layout() -> Vec<VertexAttrib>;
+}
+
+// -=-=-=-=-=-=-=-=-=-=-=-=-=-=-=-=-=-=-=-=-=-=-=-=-=-=-=-
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum LayoutMismatch {
+    /// The layout feeds a location the program has no active input for.
+    MissingLocation { field: &'static str, location: usize },
+    ComponentCount {
+        field: &'static str,
+        location: usize,
+        shader: usize,
+        layout: usize,
+    },
+    BaseType {
+        field: &'static str,
+        location: usize,
+        shader: AttribBaseType,
+        layout: AttribBaseType,
+    },
+}
+
+impl fmt::Display for LayoutMismatch {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            LayoutMismatch::MissingLocation { field, location } => write!(
+                f,
+                "field {} at location {}: program has no active input at this location",
+                field, location
+            ),
+            LayoutMismatch::ComponentCount {
+                field,
+                location,
+                shader,
+                layout,
+            } => write!(
+                f,
+                "field {} at location {}: shader expects {} components, layout provides {}",
+                field, location, shader, layout
+            ),
+            LayoutMismatch::BaseType {
+                field,
+                location,
+                shader,
+                layout,
+            } => write!(
+                f,
+                "field {} at location {}: shader expects {} input, layout provides {}",
+                field, location, shader, layout
+            ),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct LayoutMismatches(pub Vec<LayoutMismatch>);
+
+impl fmt::Display for LayoutMismatches {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for m in &self.0 {
+            writeln!(f, "  {}", m)?;
+        }
+        Ok(())
+    }
+}
+
+/// Compares `layout` against the active attributes of a program and collects
+/// everything that does not line up.
+pub fn check_layout(interface: &ProgramInterface, layout: &[VertexAttrib]) -> Vec<LayoutMismatch> {
+    // location -> (base type, components) as declared in the shader. Matrices
+    // and arrays occupy one location per column / element.
+    let mut inputs: HashMap<usize, (AttribBaseType, usize)> = HashMap::new();
+    for a in &interface.attributes {
+        if a.location < 0 {
+            // built-ins like gl_VertexID
+            continue;
+        }
+        if let Some((base_type, components, columns)) = attribute_shape(a.gl_type) {
+            for i in 0..columns * a.size.max(1) as usize {
+                inputs.insert(a.location as usize + i, (base_type, components));
+            }
+        }
+    }
+
+    layout
+        .iter()
+        .filter_map(|attr| {
+            let (base_type, components) = match inputs.get(&attr.location) {
+                Some(&input) => input,
+                None => {
+                    return Some(LayoutMismatch::MissingLocation {
+                        field: attr.name,
+                        location: attr.location,
+                    })
+                }
+            };
+            if base_type != attr.format.base_type {
+                Some(LayoutMismatch::BaseType {
+                    field: attr.name,
+                    location: attr.location,
+                    shader: base_type,
+                    layout: attr.format.base_type,
+                })
+            } else if components != attr.format.components {
+                Some(LayoutMismatch::ComponentCount {
+                    field: attr.name,
+                    location: attr.location,
+                    shader: components,
+                    layout: attr.format.components,
+                })
+            } else {
+                None
+            }
+        })
+        .collect()
+}
+
+/// (base type, components per location, locations) of a GLSL vertex input
+/// type.
+pub fn attribute_shape(gl_type: gl::types::GLenum) -> Option<(AttribBaseType, usize, usize)> {
+    use self::AttribBaseType::*;
+    let shape = match gl_type {
+        gl::FLOAT => (Float, 1, 1),
+        gl::FLOAT_VEC2 => (Float, 2, 1),
+        gl::FLOAT_VEC3 => (Float, 3, 1),
+        gl::FLOAT_VEC4 => (Float, 4, 1),
+        gl::FLOAT_MAT2 => (Float, 2, 2),
+        gl::FLOAT_MAT3 => (Float, 3, 3),
+        gl::FLOAT_MAT4 => (Float, 4, 4),
+        gl::FLOAT_MAT2x3 => (Float, 3, 2),
+        gl::FLOAT_MAT2x4 => (Float, 4, 2),
+        gl::FLOAT_MAT3x2 => (Float, 2, 3),
+        gl::FLOAT_MAT3x4 => (Float, 4, 3),
+        gl::FLOAT_MAT4x2 => (Float, 2, 4),
+        gl::FLOAT_MAT4x3 => (Float, 3, 4),
+        gl::INT => (Int, 1, 1),
+        gl::INT_VEC2 => (Int, 2, 1),
+        gl::INT_VEC3 => (Int, 3, 1),
+        gl::INT_VEC4 => (Int, 4, 1),
+        gl::UNSIGNED_INT => (UnsignedInt, 1, 1),
+        gl::UNSIGNED_INT_VEC2 => (UnsignedInt, 2, 1),
+        gl::UNSIGNED_INT_VEC3 => (UnsignedInt, 3, 1),
+        gl::UNSIGNED_INT_VEC4 => (UnsignedInt, 4, 1),
+        gl::DOUBLE => (Double, 1, 1),
+        gl::DOUBLE_VEC2 => (Double, 2, 1),
+        gl::DOUBLE_VEC3 => (Double, 3, 1),
+        gl::DOUBLE_VEC4 => (Double, 4, 1),
+        gl::DOUBLE_MAT2 => (Double, 2, 2),
+        gl::DOUBLE_MAT3 => (Double, 3, 3),
+        gl::DOUBLE_MAT4 => (Double, 4, 4),
+        gl::DOUBLE_MAT2x3 => (Double, 3, 2),
+        gl::DOUBLE_MAT2x4 => (Double, 4, 2),
+        gl::DOUBLE_MAT3x2 => (Double, 2, 3),
+        gl::DOUBLE_MAT3x4 => (Double, 4, 3),
+        gl::DOUBLE_MAT4x2 => (Double, 2, 4),
+        gl::DOUBLE_MAT4x3 => (Double, 3, 4),
+        _ => return None,
+    };
+    Some(shape)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::render_gl::data;
+    use crate::render_gl::ActiveVariable;
+    use nalgebra as na;
+    use render_gl_derive::VertexAttribPointers;
+
+    #[derive(VertexAttribPointers, Copy, Clone)]
+    #[repr(C, packed)]
+    struct Simple {
+        #[location = 0]
+        pos: data::f32_f32_f32,
+        #[location = 1]
+        clr: data::u8_u8_u8_u8_float,
+    }
+
+    #[derive(VertexAttribPointers, Copy, Clone)]
+    #[repr(C, packed)]
+    struct Instance {
+        #[location = 2]
+        model: na::Matrix4<f32>,
+        #[location = 6]
+        offsets: [data::f32_f32; 3],
+        #[location = 9]
+        id: data::u32_,
+    }
+
+    #[derive(VertexAttribPointers, Copy, Clone)]
+    #[repr(C, packed)]
+    struct Auto {
+        pos: data::f32_f32_f32,
+        #[location = 4]
+        uv: data::f32_f32,
+        weights: [f32; 3],
+        clr: data::u8_u8_u8_u8_float,
+    }
+
+    #[derive(VertexAttribPointers, Copy, Clone)]
+    #[repr(C, packed)]
+    struct Tuple(data::f32_f32, #[location = 3] data::f32_, data::u32_);
+
+    #[derive(VertexAttribPointers, Copy, Clone)]
+    #[repr(C, packed)]
+    struct Nested {
+        id: data::u32_,
+        #[flatten]
+        auto: Auto,
+        size: data::f32_,
+        #[location = 12]
+        #[flatten]
+        tuple: Tuple,
+    }
+
+    #[derive(VertexAttribPointers, Copy, Clone)]
+    #[repr(C, packed)]
+    #[divisor = 1]
+    struct PerInstance {
+        offset: data::f32_f32,
+        #[divisor = 4]
+        color: data::u8_u8_u8_u8_float,
+    }
+
+    #[derive(VertexAttribPointers, Copy, Clone)]
+    #[repr(C, packed)]
+    struct Divisors {
+        pos: data::f32_f32_f32,
+        #[flatten]
+        instance: PerInstance,
+        #[flatten]
+        #[divisor = 2]
+        every_other: PerInstance,
+        #[divisor = 3]
+        scale: data::f32_,
+    }
+
+    #[derive(VertexAttribPointers, Copy, Clone)]
+    #[repr(C, packed)]
+    #[divisor = 5]
+    struct AllInstanced {
+        #[flatten]
+        instance: PerInstance,
+        size: data::f32_,
+    }
+
+    /// (name, location, offset) of each attribute
+    fn places(layout: &[VertexAttrib]) -> Vec<(&'static str, usize, usize)> {
+        layout
+            .iter()
+            .map(|attrib| (attrib.name, attrib.location, attrib.offset))
+            .collect()
+    }
+
+    fn interface(attributes: &[(&str, gl::types::GLenum, i32, i32)]) -> ProgramInterface {
+        ProgramInterface {
+            attributes: attributes
+                .iter()
+                .map(|&(name, gl_type, size, location)| ActiveVariable {
+                    name: name.into(),
+                    gl_type,
+                    size,
+                    location,
+                })
+                .collect(),
+            uniforms: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn matching_layout() {
+        let interface = interface(&[
+            ("Position", gl::FLOAT_VEC3, 1, 0),
+            ("Color", gl::FLOAT_VEC4, 1, 1),
+            ("gl_VertexID", gl::INT, 1, -1),
+        ]);
+        assert_eq!(check_layout(&interface, &Simple::vertex_layout()), []);
+    }
+
+    #[test]
+    fn missing_location() {
+        let interface = interface(&[("Position", gl::FLOAT_VEC3, 1, 0)]);
+        assert_eq!(
+            check_layout(&interface, &Simple::vertex_layout()),
+            [LayoutMismatch::MissingLocation {
+                field: "clr",
+                location: 1,
+            }]
+        );
+    }
+
+    #[test]
+    fn component_and_type_mismatch() {
+        let interface = interface(&[
+            ("Position", gl::FLOAT_VEC2, 1, 0),
+            ("Color", gl::UNSIGNED_INT_VEC4, 1, 1),
+        ]);
+        assert_eq!(
+            check_layout(&interface, &Simple::vertex_layout()),
+            [
+                LayoutMismatch::ComponentCount {
+                    field: "pos",
+                    location: 0,
+                    shader: 2,
+                    layout: 3,
+                },
+                LayoutMismatch::BaseType {
+                    field: "clr",
+                    location: 1,
+                    shader: AttribBaseType::UnsignedInt,
+                    layout: AttribBaseType::Float,
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn matrices_and_arrays_span_locations() {
+        let interface = interface(&[
+            ("Model", gl::FLOAT_MAT4, 1, 2),
+            ("Offsets", gl::FLOAT_VEC2, 3, 6),
+            ("Id", gl::UNSIGNED_INT, 1, 9),
+        ]);
+        let layout = Instance::vertex_layout();
+        assert_eq!(layout.len(), 8);
+        assert_eq!(check_layout(&interface, &layout), []);
+
+        // a mat3 only covers three locations of three components
+        let interface = self::interface(&[
+            ("Model", gl::FLOAT_MAT3, 1, 2),
+            ("Offsets", gl::FLOAT_VEC2, 2, 6),
+            ("Id", gl::UNSIGNED_INT, 1, 9),
+        ]);
+        let mismatches = check_layout(&interface, &layout);
+        assert_eq!(
+            mismatches,
+            [
+                LayoutMismatch::ComponentCount {
+                    field: "model",
+                    location: 2,
+                    shader: 3,
+                    layout: 4,
+                },
+                LayoutMismatch::ComponentCount {
+                    field: "model",
+                    location: 3,
+                    shader: 3,
+                    layout: 4,
+                },
+                LayoutMismatch::ComponentCount {
+                    field: "model",
+                    location: 4,
+                    shader: 3,
+                    layout: 4,
+                },
+                LayoutMismatch::MissingLocation {
+                    field: "model",
+                    location: 5,
+                },
+                LayoutMismatch::MissingLocation {
+                    field: "offsets",
+                    location: 8,
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn automatic_locations_and_scalar_arrays() {
+        let layout = Auto::vertex_layout();
+        assert_eq!(
+            places(&layout),
+            [
+                ("pos", 0, 0),
+                ("uv", 4, 12),
+                ("weights", 5, 20),
+                ("weights", 6, 24),
+                ("weights", 7, 28),
+                ("clr", 8, 32),
+            ]
+        );
+        let f32_format = <data::f32_ as VertexAttribute>::FORMAT;
+        assert!(layout[2..5]
+            .iter()
+            .all(|attrib| attrib.format == f32_format));
+    }
+
+    #[test]
+    fn tuple_struct_fields_are_named_by_index() {
+        assert_eq!(
+            places(&Tuple::vertex_layout()),
+            [("0", 0, 0), ("1", 3, 8), ("2", 4, 12)]
+        );
+    }
+
+    #[test]
+    fn flattened_structs_continue_the_locations() {
+        assert_eq!(
+            places(&Nested::vertex_layout()),
+            [
+                ("id", 0, 0),
+                ("pos", 1, 4),
+                ("uv", 5, 16),
+                ("weights", 6, 24),
+                ("weights", 7, 28),
+                ("weights", 8, 32),
+                ("clr", 9, 36),
+                ("size", 10, 40),
+                ("0", 12, 44),
+                ("1", 15, 52),
+                ("2", 16, 56),
+            ]
+        );
+    }
+
+    #[test]
+    fn divisors_of_struct_field_and_flattened_struct() {
+        let divisors = |layout: Vec<VertexAttrib>| -> Vec<(&'static str, usize, u32)> {
+            layout
+                .iter()
+                .map(|attrib| (attrib.name, attrib.location, attrib.divisor))
+                .collect()
+        };
+        assert_eq!(
+            divisors(PerInstance::vertex_layout()),
+            [("offset", 0, 1), ("color", 1, 4)]
+        );
+        // the nested divisors apply unless the field sets one
+        assert_eq!(
+            divisors(Divisors::vertex_layout()),
+            [
+                ("pos", 0, 0),
+                ("offset", 1, 1),
+                ("color", 2, 4),
+                ("offset", 3, 2),
+                ("color", 4, 2),
+                ("scale", 5, 3),
+            ]
+        );
+        // a divisor on the outer struct overrides them as well
+        assert_eq!(
+            divisors(AllInstanced::vertex_layout()),
+            [("offset", 0, 5), ("color", 1, 5), ("size", 2, 5)]
+        );
+    }
+
+    #[test]
+    fn attribute_shapes() {
+        use self::AttribBaseType::*;
+        assert_eq!(attribute_shape(gl::FLOAT_MAT2x3), Some((Float, 3, 2)));
+        assert_eq!(attribute_shape(gl::INT_VEC3), Some((Int, 3, 1)));
+        assert_eq!(attribute_shape(gl::DOUBLE_MAT4), Some((Double, 4, 4)));
+        assert_eq!(attribute_shape(gl::DOUBLE_MAT3x2), Some((Double, 2, 3)));
+        assert_eq!(attribute_shape(gl::SAMPLER_2D), None);
+    }
+}
@@ -19,8 +19,6 @@ impl Viewport {
 
     pub fn set_used(&self, gl: &gl::Gl) {
         let Viewport { w, h, .. } = self;
-        unsafe {
-            gl.Viewport(0, 0, *w, *h);
-        }
+        gl.viewport(0, 0, *w, *h);
     }
 }
@@ -31,6 +31,10 @@ pub enum Error {
     Io(#[cause] io::Error),
     #[fail(display = "Failed to read CString from file that contains 0")]
     FileContainsNil,
+    #[fail(display = "Failed to decode image")]
+    ImageDecode(#[cause] png::DecodingError),
+    #[fail(display = "Failed to encode image")]
+    ImageEncode(#[cause] png::EncodingError),
 }
 
 impl From<io::Error> for Error {
@@ -39,6 +43,24 @@ impl From<io::Error> for Error {
     }
 }
 
+impl From<png::DecodingError> for Error {
+    fn from(other: png::DecodingError) -> Self {
+        match other {
+            png::DecodingError::IoError(e) => Error::Io(e),
+            other => Error::ImageDecode(other),
+        }
+    }
+}
+
+impl From<png::EncodingError> for Error {
+    fn from(other: png::EncodingError) -> Self {
+        match other {
+            png::EncodingError::IoError(e) => Error::Io(e),
+            other => Error::ImageEncode(other),
+        }
+    }
+}
+
 // -=-=-=-=-=-=-=-=-=-=-=-=-=-=-=-=-=-=-=-=-=-=-=-=-=-=-=-
 
 #[derive(Debug)]
@@ -47,6 +69,64 @@ pub struct Source {
     pub path: PathBuf,
 }
 
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum ImageColor {
+    Gray,
+    GrayAlpha,
+    Rgb,
+    Rgba,
+}
+
+impl ImageColor {
+    pub fn channels(self) -> usize {
+        match self {
+            ImageColor::Gray => 1,
+            ImageColor::GrayAlpha => 2,
+            ImageColor::Rgb => 3,
+            ImageColor::Rgba => 4,
+        }
+    }
+}
+
+/// Decoded image with 8 bits per channel, rows from top to bottom.
+#[derive(Debug)]
+pub struct Image {
+    pub width: u32,
+    pub height: u32,
+    pub color: ImageColor,
+    pub data: Vec<u8>,
+    pub path: PathBuf,
+}
+
+impl Image {
+    /// Reverses the row order, e.g. to match GL's bottom-up texture rows.
+    pub fn flip_vertically(&mut self) {
+        let row_len = self.width as usize * self.color.channels();
+        let rows = self.data.len() / row_len.max(1);
+        for y in 0..rows / 2 {
+            let (top, bottom) = self.data.split_at_mut((rows - 1 - y) * row_len);
+            top[y * row_len..(y + 1) * row_len].swap_with_slice(&mut bottom[..row_len]);
+        }
+    }
+
+    /// Writes the image as 8 bit PNG to `path`, which is not relative to the
+    /// resource root.
+    pub fn save_png(&self, path: &Path) -> Result<(), Error> {
+        let file = io::BufWriter::new(fs::File::create(path)?);
+        let mut encoder = png::Encoder::new(file, self.width, self.height);
+        encoder.set_color(match self.color {
+            ImageColor::Gray => png::ColorType::Grayscale,
+            ImageColor::GrayAlpha => png::ColorType::GrayscaleAlpha,
+            ImageColor::Rgb => png::ColorType::RGB,
+            ImageColor::Rgba => png::ColorType::RGBA,
+        });
+        encoder.set_depth(png::BitDepth::Eight);
+        let mut writer = encoder.write_header()?;
+        writer.write_image_data(&self.data)?;
+        Ok(())
+    }
+}
+
 #[derive(Debug)]
 pub struct Resources {
     root_path: PathBuf,
@@ -69,6 +149,16 @@ impl Resources {
         })
     }
 
+    pub fn from_path(root_path: &Path) -> Resources {
+        Resources {
+            root_path: root_path.to_path_buf(),
+        }
+    }
+
+    pub fn exists(&self, resource_name: &str) -> bool {
+        resource_name_to_path(&self.root_path, resource_name).is_file()
+    }
+
     pub fn load_cstring(&self, resource_name: &str) -> Result<Source, Error> {
         let path = resource_name_to_path(&self.root_path, resource_name);
         let mut file = fs::File::open(path.to_path_buf())?;
@@ -82,6 +172,30 @@ impl Resources {
             content: unsafe { ffi::CString::from_vec_unchecked(buffer) },
         })
     }
+
+    /// Decodes a PNG file. Palette and low bit depth images are expanded, 16
+    /// bit channels are reduced to 8 bit.
+    pub fn load_image(&self, resource_name: &str) -> Result<Image, Error> {
+        let path = resource_name_to_path(&self.root_path, resource_name);
+        let mut decoder = png::Decoder::new(fs::File::open(&path)?);
+        decoder.set_transformations(png::Transformations::EXPAND | png::Transformations::STRIP_16);
+        let (info, mut reader) = decoder.read_info()?;
+        let mut data: Vec<u8> = vec![0; info.buffer_size()];
+        reader.next_frame(&mut data)?;
+        let color = match info.color_type {
+            png::ColorType::Grayscale => ImageColor::Gray,
+            png::ColorType::GrayscaleAlpha => ImageColor::GrayAlpha,
+            png::ColorType::RGB | png::ColorType::Indexed => ImageColor::Rgb,
+            png::ColorType::RGBA => ImageColor::Rgba,
+        };
+        Ok(Image {
+            width: info.width,
+            height: info.height,
+            color,
+            data,
+            path,
+        })
+    }
 }
 
 // -=-=-=-=-=-=-=-=-=-=-=-=-=-=-=-=-=-=-=-=-=-=-=-=-=-=-=-
@@ -89,10 +203,21 @@ impl Resources {
 pub trait Reloadable {
     fn get_paths(&self) -> &[PathBuf];
     fn reload(&mut self, gl: &gl::Gl, res: &Resources) -> Result<(), failure::Error>;
+
+    /// Called with the file that changed. Reloads everything by default.
+    fn reload_changed(
+        &mut self,
+        gl: &gl::Gl,
+        res: &Resources,
+        _path: &Path,
+    ) -> Result<(), failure::Error> {
+        self.reload(gl, res)
+    }
 }
 
 pub struct ResourceWatcher {
     watcher: RecommendedWatcher,
+    watched: Vec<PathBuf>,
     pub rx: Receiver<notify::RawEvent>,
 }
 
@@ -100,16 +225,26 @@ impl ResourceWatcher {
     pub fn new() -> Self {
         let (tx, rx) = unbounded();
         let watcher: RecommendedWatcher = Watcher::new_immediate(tx).unwrap();
-        Self { watcher, rx }
+        Self {
+            watcher,
+            watched: Vec::new(),
+            rx,
+        }
     }
 
+    /// Watches the paths of `res`. Call it again when `res` gained paths,
+    /// the ones that are watched already are skipped.
     // pub fn add_reloadable<R: Reloadable, V: IntoIterator<Item = PathBuf> + Sized>(
     pub fn add_reloadable(&mut self, res: &dyn Reloadable) {
         println!("hmmmm {:?}", res.get_paths());
         for p in res.get_paths() {
             let p = absolute_path(p).unwrap();
+            if self.watched.contains(&p) {
+                continue;
+            }
             println!("watching {:#?}", p);
             self.watcher.watch(&p, RecursiveMode::Recursive).unwrap();
+            self.watched.push(p);
         }
     }
 }
@@ -1,8 +1,6 @@
 use crate::render_gl;
-use crate::render_gl::buffer;
-use crate::render_gl::buffer::{ArrayBuffer, VertexArray};
 use crate::render_gl::data;
-use crate::render_gl::Program;
+use crate::render_gl::{Mesh, Program, Topology};
 use crate::resources::{Reloadable, Resources};
 use failure;
 use gl;
@@ -19,8 +17,7 @@ struct Vertex {
 
 pub struct Triangle {
     program: render_gl::Program,
-    _vbo: buffer::ArrayBuffer,
-    vao: buffer::VertexArray,
+    mesh: Mesh<Vertex>,
 }
 
 impl Triangle {
@@ -40,35 +37,16 @@ impl Triangle {
             },
         ];
 
-        let vao = VertexArray::new(gl);
-        let buffer = ArrayBuffer::new(gl);
-        vao.bind();
-        buffer.bind();
-        buffer.static_draw(&vertices);
-        Vertex::vertex_attrib_pointers(&gl);
-        vao.unbind();
-        buffer.unbind();
+        let mesh = Mesh::new(gl, &vertices, Topology::Triangles);
 
-        Ok(Triangle {
-            program: Program::from_res(&gl, &res, "shaders/triangle")?,
-            vao,
-            _vbo: buffer,
-        })
+        let program = Program::from_res(&gl, &res, "shaders/triangle")?;
+        mesh.check_program(&program)?;
+
+        Ok(Triangle { program, mesh })
     }
 
-    pub fn render(&self, gl: &gl::Gl) {
-        self.program.set_used();
-        self.vao.bind();
-        unsafe {
-            gl.DrawArrays(
-                //mode
-                gl::TRIANGLES,
-                // starting index in the enabled arrays
-                0,
-                // number of incies to be rendered
-                3,
-            );
-        }
+    pub fn render(&self, _gl: &gl::Gl) {
+        self.mesh.draw(&self.program);
     }
 }
 
@@ -80,6 +58,10 @@ impl Reloadable for Triangle {
     fn reload(&mut self, gl: &gl::Gl, res: &Resources) -> Result<(), failure::Error> {
         println!("reloading triangle");
         Program::from_res(&gl, &res, "shaders/triangle")
+            .and_then(|program| {
+                self.mesh.check_program(&program)?;
+                Ok(program)
+            })
             .map(|program| self.program = program)
             .unwrap_or_else(|err| {
                 println!("Failed to reload triangle. {:?}", err);